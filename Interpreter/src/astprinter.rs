@@ -1,10 +1,123 @@
+use crate::docgen;
 use crate::expr::{Binary, Expr, ExprVisitor, Grouping, Literal, Unary, Variable};
-use crate::token::TokenLiteral;
+use crate::stmt::Stmt;
+use crate::token::{Token, TokenLiteral};
+use crate::types::Type;
 
 pub struct AstPrinter;
 
 impl AstPrinter {
-   
+    /// The program as nested lists, one statement per line, for `--ast`.
+    pub fn print(&self, statements: &[Stmt]) -> String {
+        let lines: Vec<String> = statements.iter().map(|stmt| self.statement(stmt, 0)).collect();
+        lines.join("\n")
+    }
+
+    fn statement(&self, stmt: &Stmt, depth: usize) -> String {
+        let expr = |expr: &Expr| expr.accept(self);
+        match stmt {
+            Stmt::Expression { expression } => format!("{}{}", "  ".repeat(depth), expr(expression)),
+            Stmt::Print { expressions, separator, newline, stderr } => {
+                let name = match (newline, stderr) {
+                    (_, true) => "ewrite",
+                    (false, _) => "write_inline",
+                    _ => "write",
+                };
+                let mut parts: Vec<String> = expressions.iter().map(expr).collect();
+                if let Some(separator) = separator {
+                    parts.push(format!("sep: {}", expr(separator)));
+                }
+                self.node(depth, format!("{} {}", name, parts.join(" ")), &[])
+            }
+            Stmt::Var { name, annotation, initializer } => {
+                let mut head = format!("var {}", declared(name, annotation));
+                if let Some(initializer) = initializer {
+                    head.push_str(&format!(" {}", expr(initializer)));
+                }
+                self.node(depth, head, &[])
+            }
+            Stmt::Destructure { pattern, initializer } => {
+                self.node(depth, format!("var {} {}", pattern, expr(initializer)), &[])
+            }
+            Stmt::Const { name, annotation, initializer } => {
+                self.node(depth, format!("const {} {}", declared(name, annotation), expr(initializer)), &[])
+            }
+            Stmt::Block(statements) => self.node(depth, "block".to_string(), &statements.iter().collect::<Vec<_>>()),
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                let mut children = vec![then_branch.as_ref()];
+                children.extend(else_branch.as_deref());
+                self.node(depth, format!("if {}", expr(condition)), &children)
+            }
+            Stmt::While { condition, body, .. } => self.node(depth, format!("while {}", expr(condition)), &[body]),
+            Stmt::Input { name } => self.node(depth, format!("input {}", name.lexeme), &[]),
+            Stmt::For { initializer, condition, increment, body } => {
+                let part = |e: &Option<Expr>| e.as_ref().map_or("_".to_string(), expr);
+                let mut children: Vec<&Stmt> = initializer.as_deref().into_iter().collect();
+                children.push(body);
+                self.node(depth, format!("for {} {}", part(condition), part(increment)), &children)
+            }
+            Stmt::ForIn { index, variable, iterable, body } => {
+                let names = match index {
+                    Some(index) => format!("{}, {}", index.lexeme, variable.lexeme),
+                    None => variable.lexeme.clone(),
+                };
+                self.node(depth, format!("for {} in {}", names, expr(iterable)), &[body])
+            }
+            Stmt::Function { name, params, param_types, return_type, body, .. } => {
+                let signature = docgen::signature(name, params, param_types, return_type);
+                self.node(depth, signature, &body.iter().collect::<Vec<_>>())
+            }
+            Stmt::Return { value, .. } => match value {
+                Some(value) => self.node(depth, format!("return {}", expr(value)), &[]),
+                None => self.node(depth, "return".to_string(), &[]),
+            },
+            Stmt::Yield { value, .. } => match value {
+                Some(value) => self.node(depth, format!("yield {}", expr(value)), &[]),
+                None => self.node(depth, "yield".to_string(), &[]),
+            },
+            Stmt::Match { subject, arms } => {
+                let mut out = format!("{}(match {}", "  ".repeat(depth), expr(subject));
+                for arm in arms {
+                    let head = match &arm.guard {
+                        Some(guard) => format!("{} if {}", arm.pattern, expr(guard)),
+                        None => arm.pattern.to_string(),
+                    };
+                    out.push('\n');
+                    out.push_str(&self.node(depth + 1, head, &[&arm.body]));
+                }
+                out.push(')');
+                out
+            }
+            Stmt::Enum { name, variants } => {
+                let variants: Vec<String> = variants
+                    .iter()
+                    .map(|variant| {
+                        if variant.fields.is_empty() {
+                            return variant.name.lexeme.clone();
+                        }
+                        let fields: Vec<&str> = variant.fields.iter().map(|field| field.lexeme.as_str()).collect();
+                        format!("{}({})", variant.name.lexeme, fields.join(", "))
+                    })
+                    .collect();
+                self.node(depth, format!("enum {} {}", name.lexeme, variants.join(" ")), &[])
+            }
+            Stmt::Test { name, body } => {
+                self.node(depth, format!("test {}", name.lexeme), &body.iter().collect::<Vec<_>>())
+            }
+        }
+    }
+
+    // `(head` followed by each child on its own, deeper line, then `)`
+    fn node(&self, depth: usize, head: String, children: &[&Stmt]) -> String {
+        let mut out = format!("{}({}", "  ".repeat(depth), head);
+        for child in children {
+            out.push('\n');
+            out.push_str(&self.statement(child, depth + 1));
+        }
+        out.push(')');
+        out
+    }
+
     fn parenthesize(&self, name: &str, expressions: &[&Expr]) -> String {
         let mut result = String::from("(");
         result.push_str(name);
@@ -30,6 +143,7 @@ impl ExprVisitor for AstPrinter {
         if let Some(token_literal) = expr.value.downcast_ref::<TokenLiteral>() {
             match token_literal {
                 TokenLiteral::Number(n) => return n.to_string(),
+                TokenLiteral::Decimal(d) => return format!("{}d", d),
                 TokenLiteral::String(s) => return format!("\"{}\"", s),
                 TokenLiteral::Boolean(b) => return b.to_string(),
                 TokenLiteral::Identifier(id) => return id.clone(),
@@ -56,3 +170,11 @@ impl ExprVisitor for AstPrinter {
         expr.name.lexeme.clone()
     }
 }
+
+// `name` or `name: type`
+fn declared(name: &Token, annotation: &Option<Type>) -> String {
+    match annotation {
+        Some(ty) => format!("{}: {}", name.lexeme, ty),
+        None => name.lexeme.clone(),
+    }
+}
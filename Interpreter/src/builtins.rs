@@ -0,0 +1,609 @@
+use crate::environment::Environment;
+use crate::format;
+use crate::generator::Generator;
+use crate::interpreter::{AoiArray, EnumValue, Interpreter, EXIT_SIGNAL};
+use crate::number;
+use crate::tasks::{Channel, Task};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+type Value = Arc<dyn Any + Send + Sync>;
+type NativeFn = fn(&mut Interpreter, &[Value]) -> Result<Value, String>;
+
+// Longest string `repeat()` builds, in bytes; a larger request is an error
+// rather than an allocation that takes the whole process down
+const MAX_REPEAT_LEN: usize = 10_000_000;
+
+// A function implemented in Rust and callable from Aoi code
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub min_arity: usize,
+    pub max_arity: usize,
+    pub function: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+        if arguments.len() < self.min_arity || arguments.len() > self.max_arity {
+            return Err(format!(
+                "{}() expected {} arguments but got {}.",
+                self.name,
+                self.arity(),
+                arguments.len()
+            ));
+        }
+        (self.function)(interpreter, arguments)
+    }
+
+    /// How many arguments the function takes, such as "1 to 3".
+    pub fn arity(&self) -> String {
+        if self.min_arity == self.max_arity {
+            self.min_arity.to_string()
+        } else if self.max_arity == usize::MAX {
+            format!("at least {}", self.min_arity)
+        } else {
+            format!("{} to {}", self.min_arity, self.max_arity)
+        }
+    }
+}
+
+/// Defines every built-in function in the global environment.
+pub fn define_globals(environment: &mut Environment) {
+    let natives: &[(&'static str, usize, usize, NativeFn)] = &[
+        ("len", 1, 1, len),
+        ("substring", 2, 3, substring),
+        ("split", 2, 2, split),
+        ("join", 1, 2, join),
+        ("trim", 1, 1, trim),
+        ("upper", 1, 1, upper),
+        ("lower", 1, 1, lower),
+        ("contains", 2, 2, contains),
+        ("starts_with", 2, 2, starts_with),
+        ("ends_with", 2, 2, ends_with),
+        ("find", 2, 2, find),
+        ("replace", 3, 3, replace),
+        ("repeat", 2, 2, repeat),
+        ("chars", 1, 1, chars),
+        ("ord", 1, 1, ord),
+        ("chr", 1, 1, chr),
+        ("str", 1, 2, str),
+        ("format", 1, usize::MAX, format),
+        ("decimal", 1, 1, decimal),
+        ("float", 1, 1, float),
+        ("round", 1, 3, round),
+        ("map", 2, 2, map),
+        ("filter", 2, 2, filter),
+        ("reduce", 2, 3, reduce),
+        ("any", 2, 2, any),
+        ("all", 2, 2, all),
+        ("sort", 1, 2, sort),
+        ("reverse", 1, 1, reverse),
+        ("index_of", 2, 2, index_of),
+        ("zip", 2, 2, zip),
+        ("tag", 1, 1, tag),
+        ("payload", 1, 1, payload),
+        ("next", 1, 1, next),
+        ("spawn", 1, usize::MAX, spawn),
+        ("channel", 0, 1, channel),
+        ("send", 2, 2, send),
+        ("recv", 1, 1, recv),
+        ("close", 1, 1, close),
+        ("assert", 1, 2, assert),
+        ("env", 1, 1, env),
+        ("exit", 0, 1, exit),
+        ("help", 1, 1, help),
+    ];
+
+    for &(name, min_arity, max_arity, function) in natives {
+        environment.define(
+            name.to_string(),
+            Arc::new(NativeFunction {
+                name,
+                min_arity,
+                max_arity,
+                function,
+            }),
+        );
+    }
+}
+
+fn string_arg<'a>(arguments: &'a [Value], position: usize, name: &str) -> Result<&'a String, String> {
+    arguments[position]
+        .downcast_ref::<String>()
+        .ok_or_else(|| format!("{}() expects a string as argument {}.", name, position + 1))
+}
+
+fn array_arg<'a>(arguments: &'a [Value], position: usize, name: &str) -> Result<&'a AoiArray, String> {
+    arguments[position]
+        .downcast_ref::<AoiArray>()
+        .ok_or_else(|| format!("{}() expects an array as argument {}.", name, position + 1))
+}
+
+/// Looks up `receiver.name`, for built-ins that can be called as methods.
+/// The receiver becomes the first argument, so `gen.next()` is `next(gen)`.
+pub(crate) fn method(receiver: &Value, name: &str) -> Option<NativeFunction> {
+    let methods: &[(&'static str, usize, usize, NativeFn)] = if receiver.is::<Generator>() {
+        &[("next", 1, 1, next)]
+    } else if receiver.is::<Task>() {
+        &[("join", 1, 1, join)]
+    } else if receiver.is::<Channel>() {
+        &[("send", 2, 2, send), ("recv", 1, 1, recv), ("close", 1, 1, close)]
+    } else {
+        &[]
+    };
+
+    methods
+        .iter()
+        .find(|(method, ..)| *method == name)
+        .map(|&(name, min_arity, max_arity, function)| NativeFunction {
+            name,
+            min_arity,
+            max_arity,
+            function,
+        })
+}
+
+fn enum_arg<'a>(arguments: &'a [Value], name: &str) -> Result<&'a EnumValue, String> {
+    arguments[0]
+        .downcast_ref::<EnumValue>()
+        .ok_or_else(|| format!("{}() expects an enum value.", name))
+}
+
+// Snapshot of an array argument, so callbacks may modify the array safely
+fn array_items(arguments: &[Value], name: &str) -> Result<Vec<Value>, String> {
+    Ok(array_arg(arguments, 0, name)?.elements.borrow().clone())
+}
+
+fn integer_arg(interpreter: &Interpreter, arguments: &[Value], position: usize, name: &str) -> Result<i64, String> {
+    interpreter.integer_value(&arguments[position], &format!("Argument {} of {}()", position + 1, name))
+}
+
+// Character position of `needle` in `haystack`, or -1
+fn char_position(haystack: &str, needle: &str) -> f64 {
+    match haystack.find(needle) {
+        Some(byte_index) => haystack[..byte_index].chars().count() as f64,
+        None => -1.0,
+    }
+}
+
+fn len(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if let Some(s) = arguments[0].downcast_ref::<String>() {
+        Ok(Arc::new(s.chars().count() as f64))
+    } else if let Some(array) = arguments[0].downcast_ref::<AoiArray>() {
+        Ok(Arc::new(array.len() as f64))
+    } else {
+        Err("len() expects a string or an array.".to_string())
+    }
+}
+
+// substring(s, start, end?) with character positions; `end` is exclusive
+fn substring(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "substring")?;
+    let start = integer_arg(interpreter, arguments, 1, "substring")?;
+    let end = if arguments.len() > 2 {
+        Some(integer_arg(interpreter, arguments, 2, "substring")?)
+    } else {
+        None
+    };
+    Ok(Arc::new(interpreter.slice_string(s, Some(start), end, 1)?))
+}
+
+fn split(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "split")?;
+    let separator = string_arg(arguments, 1, "split")?;
+    let parts: Vec<Value> = if separator.is_empty() {
+        s.chars().map(|c| Arc::new(c.to_string()) as Value).collect()
+    } else {
+        s.split(separator.as_str()).map(|part| Arc::new(part.to_string()) as Value).collect()
+    };
+    Ok(Arc::new(AoiArray::new(parts)))
+}
+
+// join(arr, sep) joins strings; join(task) waits for a task and returns its result
+fn join(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if let Some(task) = arguments[0].downcast_ref::<Task>() {
+        if arguments.len() > 1 {
+            return Err("join() expected 1 arguments but got 2.".to_string());
+        }
+        return interpreter.join_task(task);
+    }
+
+    if arguments.len() < 2 {
+        return Err("join() expected 2 arguments but got 1.".to_string());
+    }
+    let array = array_arg(arguments, 0, "join")?;
+    let separator = string_arg(arguments, 1, "join")?;
+    let parts: Vec<String> = array
+        .elements
+        .borrow()
+        .iter()
+        .map(|element| interpreter.stringify(element))
+        .collect();
+    Ok(Arc::new(parts.join(separator)))
+}
+
+fn trim(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    Ok(Arc::new(string_arg(arguments, 0, "trim")?.trim().to_string()))
+}
+
+fn upper(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    Ok(Arc::new(string_arg(arguments, 0, "upper")?.to_uppercase()))
+}
+
+fn lower(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    Ok(Arc::new(string_arg(arguments, 0, "lower")?.to_lowercase()))
+}
+
+fn contains(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "contains")?;
+    let needle = string_arg(arguments, 1, "contains")?;
+    Ok(Arc::new(s.contains(needle.as_str())))
+}
+
+fn starts_with(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "starts_with")?;
+    let prefix = string_arg(arguments, 1, "starts_with")?;
+    Ok(Arc::new(s.starts_with(prefix.as_str())))
+}
+
+fn ends_with(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "ends_with")?;
+    let suffix = string_arg(arguments, 1, "ends_with")?;
+    Ok(Arc::new(s.ends_with(suffix.as_str())))
+}
+
+// find(s, sub) gives the position of `sub`; find(arr, fn) gives the first element `fn` accepts, or nil
+fn find(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if arguments[0].is::<AoiArray>() {
+        for item in array_items(arguments, "find")? {
+            if interpreter.call_predicate(&arguments[1], item.clone(), "find")? {
+                return Ok(item);
+            }
+        }
+        return Ok(Arc::new(()));
+    }
+
+    let s = string_arg(arguments, 0, "find")?;
+    let needle = string_arg(arguments, 1, "find")?;
+    Ok(Arc::new(char_position(s, needle)))
+}
+
+fn replace(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "replace")?;
+    let from = string_arg(arguments, 1, "replace")?;
+    let to = string_arg(arguments, 2, "replace")?;
+    if from.is_empty() {
+        return Err("replace() cannot replace an empty string.".to_string());
+    }
+    Ok(Arc::new(s.replace(from.as_str(), to)))
+}
+
+fn repeat(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "repeat")?;
+    let count = integer_arg(interpreter, arguments, 1, "repeat")?;
+    if count < 0 {
+        return Err("repeat() count cannot be negative.".to_string());
+    }
+    let too_long = usize::try_from(count)
+        .ok()
+        .and_then(|count| s.len().checked_mul(count))
+        .is_none_or(|len| len > MAX_REPEAT_LEN);
+    if too_long {
+        return Err(format!("repeat() result would be longer than {} bytes.", MAX_REPEAT_LEN));
+    }
+    Ok(Arc::new(s.repeat(count as usize)))
+}
+
+fn chars(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "chars")?;
+    let characters = s.chars().map(|c| Arc::new(c.to_string()) as Value).collect();
+    Ok(Arc::new(AoiArray::new(characters)))
+}
+
+fn ord(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let s = string_arg(arguments, 0, "ord")?;
+    let mut characters = s.chars();
+    match (characters.next(), characters.next()) {
+        (Some(c), None) => Ok(Arc::new(c as u32 as f64)),
+        _ => Err("ord() expects a single character.".to_string()),
+    }
+}
+
+fn chr(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let code = integer_arg(interpreter, arguments, 0, "chr")?;
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| Arc::new(c.to_string()) as Value)
+        .ok_or_else(|| format!("chr() got an invalid character code {}.", code))
+}
+
+// str(value) converts anything to text; str(n, radix) writes a whole number in base 2 to 36
+fn str(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if arguments.len() == 1 {
+        return Ok(Arc::new(interpreter.stringify(&arguments[0])));
+    }
+
+    let n = interpreter.exact_integer_value(&arguments[0], "Argument 1 of str()")?;
+    let radix = integer_arg(interpreter, arguments, 1, "str")?;
+    if !(2..=36).contains(&radix) {
+        return Err(format!("str() radix must be between 2 and 36, got {}.", radix));
+    }
+    Ok(Arc::new(n.to_str_radix(radix as u32)))
+}
+
+fn decimal(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    Ok(Arc::new(number::to_decimal(&arguments[0])?))
+}
+
+// format(template, ...args) fills `{}`, `{0}` and `{name}` placeholders
+fn format(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let template = string_arg(arguments, 0, "format")?;
+    Ok(Arc::new(format::format_template(interpreter, template, &arguments[1..])?))
+}
+
+// float(x) turns a decimal, big integer or numeric string into a regular number
+fn float(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if let Some(text) = arguments[0].downcast_ref::<String>() {
+        return text
+            .trim()
+            .parse::<f64>()
+            .map(|n| Arc::new(n) as Value)
+            .map_err(|_| format!("Cannot convert '{}' to a number.", text));
+    }
+    number::as_float(&arguments[0])
+        .map(|n| Arc::new(n) as Value)
+        .ok_or_else(|| "float() expects a number or a string.".to_string())
+}
+
+// round(x, places = 0, mode = "half_up"); decimals stay decimals
+fn round(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if !number::is_number(&arguments[0]) {
+        return Err("round() expects a number as argument 1.".to_string());
+    }
+    let places = match arguments.get(1) {
+        Some(_) => integer_arg(interpreter, arguments, 1, "round")?,
+        None => 0,
+    };
+    let places = u32::try_from(places)
+        .ok()
+        .filter(|places| *places <= 28)
+        .ok_or_else(|| format!("round() places must be between 0 and 28, got {}.", places))?;
+    let mode = match arguments.get(2) {
+        Some(_) => string_arg(arguments, 2, "round")?.as_str(),
+        None => "half_up",
+    };
+    number::round(&arguments[0], places, mode)
+}
+
+fn map(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let mut mapped = Vec::new();
+    for item in array_items(arguments, "map")? {
+        mapped.push(interpreter.call_value(&arguments[1], vec![item])?);
+    }
+    Ok(Arc::new(AoiArray::new(mapped)))
+}
+
+fn filter(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let mut kept = Vec::new();
+    for item in array_items(arguments, "filter")? {
+        if interpreter.call_predicate(&arguments[1], item.clone(), "filter")? {
+            kept.push(item);
+        }
+    }
+    Ok(Arc::new(AoiArray::new(kept)))
+}
+
+// reduce(arr, fn, initial?) folds from the left; without `initial` the first element starts the fold
+fn reduce(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let mut items = array_items(arguments, "reduce")?.into_iter();
+    let mut accumulator = match arguments.get(2) {
+        Some(initial) => initial.clone(),
+        None => items
+            .next()
+            .ok_or_else(|| "reduce() of an empty array needs an initial value.".to_string())?,
+    };
+    for item in items {
+        accumulator = interpreter.call_value(&arguments[1], vec![accumulator, item])?;
+    }
+    Ok(accumulator)
+}
+
+fn any(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    for item in array_items(arguments, "any")? {
+        if interpreter.call_predicate(&arguments[1], item, "any")? {
+            return Ok(Arc::new(true));
+        }
+    }
+    Ok(Arc::new(false))
+}
+
+fn all(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    for item in array_items(arguments, "all")? {
+        if !interpreter.call_predicate(&arguments[1], item, "all")? {
+            return Ok(Arc::new(false));
+        }
+    }
+    Ok(Arc::new(true))
+}
+
+// sort(arr, cmp?) returns a new, stably sorted array. `cmp(a, b)` returns a
+// negative number when `a` comes first, a positive one when `b` does, or 0.
+fn sort(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let items = array_items(arguments, "sort")?;
+    let comparator = arguments.get(1).cloned();
+    let sorted = merge_sort(interpreter, items, &comparator)?;
+    Ok(Arc::new(AoiArray::new(sorted)))
+}
+
+// Merge sort, because comparisons can fail and `slice::sort_by` cannot report that
+fn merge_sort(interpreter: &mut Interpreter, mut items: Vec<Value>, comparator: &Option<Value>) -> Result<Vec<Value>, String> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(interpreter, items, comparator)?;
+    let right = merge_sort(interpreter, right, comparator)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Taking from the left on ties keeps the sort stable
+        if compare_for_sort(interpreter, a, b, comparator)? != Ordering::Greater {
+            merged.push(left.next().expect("peeked"));
+        } else {
+            merged.push(right.next().expect("peeked"));
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+fn compare_for_sort(interpreter: &mut Interpreter, a: &Value, b: &Value, comparator: &Option<Value>) -> Result<Ordering, String> {
+    let Some(comparator) = comparator else {
+        return interpreter.compare_values(a, b);
+    };
+
+    let result = interpreter.call_value(comparator, vec![a.clone(), b.clone()])?;
+    let n = interpreter
+        .as_number(&result)
+        .ok_or_else(|| "sort() comparator must return a number.".to_string())?;
+    Ok(n.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+}
+
+fn reverse(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let mut items = array_items(arguments, "reverse")?;
+    items.reverse();
+    Ok(Arc::new(AoiArray::new(items)))
+}
+
+fn index_of(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    for (i, item) in array_items(arguments, "index_of")?.iter().enumerate() {
+        if interpreter.values_equal(item, &arguments[1])? {
+            return Ok(Arc::new(i as f64));
+        }
+    }
+    Ok(Arc::new(-1.0))
+}
+
+// zip(a, b) pairs up elements, stopping at the shorter array
+fn zip(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let left = array_items(arguments, "zip")?;
+    let right = array_arg(arguments, 1, "zip")?.elements.borrow().clone();
+    let pairs = left
+        .into_iter()
+        .zip(right)
+        .map(|(a, b)| Arc::new(AoiArray::new(vec![a, b])) as Value)
+        .collect();
+    Ok(Arc::new(AoiArray::new(pairs)))
+}
+
+// tag(Shape.Circle(2)) is "Circle"
+fn tag(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    Ok(Arc::new(enum_arg(arguments, "tag")?.variant.clone()))
+}
+
+// payload(Shape.Rect(2, 3)) is [2, 3]
+fn payload(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    Ok(Arc::new(AoiArray::new(enum_arg(arguments, "payload")?.payload.clone())))
+}
+
+// next(gen) runs a generator to its next `yield`; nil once it has finished
+fn next(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let generator = arguments[0]
+        .downcast_ref::<Generator>()
+        .ok_or_else(|| "next() expects a generator.".to_string())?;
+    Ok(interpreter.resume_generator(generator)?.unwrap_or_else(|| Arc::new(())))
+}
+
+// spawn(fn, args...) starts `fn(args...)` as a task
+fn spawn(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    interpreter.spawn_task(arguments[0].clone(), arguments[1..].to_vec())
+}
+
+// channel() is unbounded; channel(n) holds at most n values before send() waits
+fn channel(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let capacity = match arguments.first() {
+        Some(value) => {
+            let capacity = interpreter.integer_value(value, "Channel capacity")?;
+            if capacity < 1 {
+                return Err("channel() capacity must be at least 1.".to_string());
+            }
+            Some(capacity as usize)
+        }
+        None => None,
+    };
+    Ok(Arc::new(Channel::new(capacity)))
+}
+
+fn channel_arg<'a>(arguments: &'a [Value], name: &str) -> Result<&'a Channel, String> {
+    arguments[0]
+        .downcast_ref::<Channel>()
+        .ok_or_else(|| format!("{}() expects a channel.", name))
+}
+
+fn send(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    interpreter.send(channel_arg(arguments, "send")?, arguments[1].clone())?;
+    Ok(Arc::new(()))
+}
+
+// recv(ch) waits for a value; nil once the channel is closed and empty
+fn recv(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let value = interpreter.receive(channel_arg(arguments, "recv")?)?;
+    Ok(value.unwrap_or_else(|| Arc::new(())))
+}
+
+fn close(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    interpreter.close_channel(channel_arg(arguments, "close")?);
+    Ok(Arc::new(()))
+}
+
+// assert(condition, message) fails the program, or the enclosing test, when
+// the condition is falsy
+fn assert(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if interpreter.condition(&arguments[0])? {
+        return Ok(Arc::new(()));
+    }
+    match arguments.get(1) {
+        Some(message) => Err(format!("Assertion failed: {}", interpreter.stringify(message))),
+        None => Err("Assertion failed.".to_string()),
+    }
+}
+
+// env(name) reads an environment variable, or gives nil when it is not set
+fn env(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    if interpreter.is_sandboxed() {
+        return Err("env() is not available on the server.".to_string());
+    }
+    let name = string_arg(arguments, 0, "env")?;
+    match std::env::var(name) {
+        Ok(value) => Ok(Arc::new(value)),
+        Err(_) => Ok(Arc::new(())),
+    }
+}
+
+// exit(code = 0) ends the program, tasks included, with the given exit status
+fn exit(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let code = match arguments.first() {
+        Some(_) => integer_arg(interpreter, arguments, 0, "exit")?,
+        None => 0,
+    };
+    if !(0..=255).contains(&code) {
+        return Err(format!("exit() code must be between 0 and 255, got {}.", code));
+    }
+    Err(format!("{}{}", EXIT_SIGNAL, code))
+}
+
+// help(function) prints the function's signature and doc comment
+fn help(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+    let Some(text) = interpreter.help_text(&arguments[0]) else {
+        return Err(format!("help() expects a function, got {}.", interpreter.type_name(&arguments[0])));
+    };
+    interpreter.print(&format!("{}\n", text));
+    Ok(Arc::new(()))
+}
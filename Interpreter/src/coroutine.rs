@@ -0,0 +1,219 @@
+use std::any::Any;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+
+// Stackful coroutines that tasks run on. A coroutine body runs
+// on its own thread so it can be suspended anywhere inside `visit_stmt`, but
+// control is handed back and forth: the resumer blocks while the body runs and
+// the body blocks while suspended. The interpreter's `Rc` state is therefore
+// never touched by two threads at once.
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+// The tree-walker recurses deeply, so every thread running Aoi code gets a
+// large stack. Only the pages actually used are backed by memory.
+pub(crate) const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// Error used to unwind the body of a coroutine that is being shut down
+pub(crate) const CANCEL_SIGNAL: &str = "Cancel:";
+
+// What a coroutine did when it last gave control back
+pub(crate) enum Step {
+    Suspended,
+    Finished(Value),
+}
+
+// Messages from the body to the resumer, with the output written meanwhile
+enum Event {
+    Suspended(String),
+    Finished(Result<Value, String>, String),
+}
+
+// The body's end of the hand-off, kept by the interpreter running it
+pub(crate) struct Link {
+    events: Sender<Event>,
+    resume: Receiver<()>,
+}
+
+impl Link {
+    /// Gives control back to the resumer and blocks until resumed again.
+    pub(crate) fn suspend(&self, output: String) -> Result<(), String> {
+        self.events
+            .send(Event::Suspended(output))
+            .map_err(|_| CANCEL_SIGNAL.to_string())?;
+        self.resume.recv().map_err(|_| CANCEL_SIGNAL.to_string())
+    }
+}
+
+// The body closure owns interpreter state built on `Rc`, so it is not `Send`,
+// yet it has to move to the coroutine's thread. That is sound only because of
+// the strict hand-off:
+// - The thread takes the closure without touching it, and waits for the first
+//   `resume` before running it.
+// - From then on one side runs at a time. The resumer blocks in `resume` until
+//   the body suspends or finishes; the body blocks in `Link::suspend` until it
+//   is resumed again.
+// - A body that is cancelled unwinds on its own thread while `finish` waits
+//   for that thread to exit.
+// So no `Rc` is ever used by two threads at once. This is the only place tasks
+// get around `Send`, and it wraps nothing but a `Pending`.
+struct HandOff(Pending);
+
+// SAFETY: the hand-off above keeps the `Rc`s in `Pending` on one thread at a time
+unsafe impl Send for HandOff {}
+
+impl HandOff {
+    fn into_inner(self) -> Pending {
+        self.0
+    }
+}
+
+/// Runs `body` on a thread with a `STACK_SIZE` stack and waits for its result.
+pub(crate) fn on_large_stack<T: Send>(body: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, body)
+            .expect("Failed to start the interpreter thread");
+        thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+type Body = Box<dyn FnOnce(Link) -> (Result<Value, String>, String)>;
+
+// What the thread gets once the coroutine is first resumed
+struct Pending {
+    body: Body,
+    link: Link,
+}
+
+pub(crate) struct Coroutine {
+    pending: Option<HandOff>, // Until the first `resume` starts the thread
+    resume: Option<Sender<()>>, // Dropped to cancel a suspended body
+    events: Receiver<Event>,
+    thread: Option<JoinHandle<()>>,
+    finished: bool,
+}
+
+impl Coroutine {
+    /// Prepares `body` to run on its own thread, which is only started by the first `resume`.
+    /// The body returns its result together with any output it has not handed over yet.
+    pub(crate) fn spawn<F>(body: F) -> Coroutine
+    where
+        F: FnOnce(Link) -> (Result<Value, String>, String) + 'static,
+    {
+        let (resume_tx, resume_rx) = mpsc::channel();
+        let (events_tx, events_rx) = mpsc::channel();
+
+        Coroutine {
+            pending: Some(HandOff(Pending {
+                body: Box::new(body),
+                link: Link {
+                    events: events_tx,
+                    resume: resume_rx,
+                },
+            })),
+            resume: Some(resume_tx),
+            events: events_rx,
+            thread: None,
+            finished: false,
+        }
+    }
+
+    fn start(&mut self, pending: HandOff) -> Result<(), String> {
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let Pending { body, link } = pending.into_inner();
+                if link.resume.recv().is_err() {
+                    return; // Cancelled before it ever ran
+                }
+                let finished = link.events.clone();
+                let (result, output) = body(link);
+                let _ = finished.send(Event::Finished(result, output));
+            })
+            .map_err(|e| format!("Cannot start task: {}", e))?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Runs the body until it next suspends or finishes. Also returns the
+    /// output the body wrote in the meantime.
+    pub(crate) fn resume(&mut self) -> (Result<Step, String>, String) {
+        if self.finished {
+            return (Ok(Step::Finished(Arc::new(()))), String::new());
+        }
+        if let Some(pending) = self.pending.take() {
+            if let Err(err) = self.start(pending) {
+                self.finish();
+                return (Err(err), String::new());
+            }
+        }
+
+        let sent = self.resume.as_ref().is_some_and(|resume| resume.send(()).is_ok());
+        let event = if sent { self.events.recv().ok() } else { None };
+        match event {
+            Some(Event::Suspended(output)) => (Ok(Step::Suspended), output),
+            Some(Event::Finished(result, output)) => {
+                self.finish();
+                (result.map(Step::Finished), output)
+            }
+            None => {
+                self.finish();
+                (Err("Coroutine stopped unexpectedly.".to_string()), String::new())
+            }
+        }
+    }
+
+    /// Unwinds a suspended body and waits for its thread to exit.
+    pub(crate) fn cancel(&mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.pending = None;
+        self.resume = None;
+        if let Some(thread) = self.thread.take() {
+            // A body can drop the last reference to its own coroutine while unwinding
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for Coroutine {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// Every coroutine started during a run. Ones still suspended when the program
+// ends (for example because a global refers to them) are shut down then.
+#[derive(Clone, Default)]
+pub(crate) struct Registry(Arc<Mutex<Vec<Weak<Mutex<Coroutine>>>>>);
+
+impl Registry {
+    pub(crate) fn register(&self, coroutine: &Arc<Mutex<Coroutine>>) {
+        let mut list = self.0.lock().expect("coroutine registry poisoned");
+        list.retain(|weak| weak.strong_count() > 0);
+        list.push(Arc::downgrade(coroutine));
+    }
+
+    pub(crate) fn shutdown(&self) {
+        let list = std::mem::take(&mut *self.0.lock().expect("coroutine registry poisoned"));
+        for weak in list {
+            if let Some(coroutine) = weak.upgrade() {
+                if let Ok(mut coroutine) = coroutine.lock() {
+                    coroutine.cancel();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::parser::Parser;
+use crate::stmt::Stmt;
+use crate::token::{Token, Tokensizer};
+use crate::types::Type;
+
+// `aoi doc`: a reference for the functions a file declares, built from the
+// `///` comments in front of them:
+//
+//   /// Area of a rectangle with sides `w` and `h`.
+//   fun area(w: num, h: num): num { ... }
+
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+// One documented declaration
+struct Entry {
+    name: String,
+    signature: String,
+    doc: Option<String>,
+}
+
+/// The declaration line of a function, such as `fun area(w: num, h: num): num`.
+pub(crate) fn signature(name: &Token, params: &[Token], param_types: &[Option<Type>], return_type: &Option<Type>) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .zip(param_types)
+        .map(|(param, ty)| match ty {
+            Some(ty) => format!("{}: {}", param.lexeme, ty),
+            None => param.lexeme.clone(),
+        })
+        .collect();
+    match return_type {
+        Some(ty) => format!("fun {}({}): {}", name.lexeme, params.join(", "), ty),
+        None => format!("fun {}({})", name.lexeme, params.join(", ")),
+    }
+}
+
+/// Renders the reference for the program in `source`, titled with its file name.
+pub fn document(filename: &str, source: &str, format: Format) -> Result<String, String> {
+    let tokens = Tokensizer::new(source.to_string()).tokenize().map_err(|errors| errors.join("\n"))?;
+    let statements = Parser::new(tokens).parse().map_err(|errors| errors.join("\n"))?;
+    let entries = entries(&statements);
+    Ok(match format {
+        Format::Markdown => markdown(filename, &entries),
+        Format::Html => html(filename, &entries),
+    })
+}
+
+// Top-level functions, in the order they are declared
+fn entries(statements: &[Stmt]) -> Vec<Entry> {
+    statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Function { name, params, param_types, return_type, doc, .. } => Some(Entry {
+                name: name.lexeme.clone(),
+                signature: signature(name, params, param_types, return_type),
+                doc: doc.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn markdown(title: &str, entries: &[Entry]) -> String {
+    let mut out = format!("# {}\n", title);
+    if entries.is_empty() {
+        out.push_str("\nNo functions.\n");
+    }
+    for entry in entries {
+        out.push_str(&format!("\n## {}\n\n```aoi\n{}\n```\n\n", entry.name, entry.signature));
+        match &entry.doc {
+            Some(doc) => out.push_str(&format!("{}\n", doc)),
+            None => out.push_str("*No documentation.*\n"),
+        }
+    }
+    out
+}
+
+fn html(title: &str, entries: &[Entry]) -> String {
+    let title = escape(title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+    if entries.is_empty() {
+        out.push_str("<p>No functions.</p>\n");
+    }
+    for entry in entries {
+        let name = escape(&entry.name);
+        out.push_str(&format!("<section id=\"{}\">\n<h2>{}</h2>\n", name, name));
+        out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&entry.signature)));
+        match &entry.doc {
+            // Blank lines separate paragraphs
+            Some(doc) => {
+                for paragraph in doc.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+                    out.push_str(&format!("<p>{}</p>\n", inline_code(&escape(paragraph))));
+                }
+            }
+            None => out.push_str("<p><em>No documentation.</em></p>\n"),
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// `code` spans in doc comments become <code> elements
+fn inline_code(text: &str) -> String {
+    let parts: Vec<&str> = text.split('`').collect();
+    if parts.len().is_multiple_of(2) {
+        return text.to_string(); // An unmatched backtick, left as written
+    }
+    parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| if index % 2 == 1 { format!("<code>{}</code>", part) } else { part.to_string() })
+        .collect()
+}
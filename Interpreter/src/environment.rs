@@ -9,6 +9,7 @@ use std::sync::Arc;
 #[derive(Default)]
 pub struct Environment {
     values: HashMap<String, Arc<dyn Any + Send + Sync>>, // Stores variables & functions
+    constants: HashMap<String, usize>,                   // Constant names -> line of declaration
     enclosing: Option<Rc<RefCell<Environment>>>,         // For nested scopes
 }
 
@@ -29,6 +30,7 @@ impl Clone for Environment {
         }
         Environment {
             values: cloned_values,
+            constants: self.constants.clone(),
             enclosing: self.enclosing.clone(),
         }
     }
@@ -39,18 +41,35 @@ impl Environment {
     pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
         Environment {
             values: HashMap::new(),
+            constants: HashMap::new(),
             enclosing,
         }
     }
 
-    /// Creates a new environment with an enclosing scope (nested scope).
-
     /// Defines a new variable or updates an existing one in the current scope.
     pub fn define(&mut self, name: String, value: Arc<dyn Any + Send + Sync>) {
         self.values.insert(name, value);
     }
 
-    /// Defines a built-in (native) function in the environment.
+    /// Defines a constant in the current scope; later assignments to it fail.
+    pub fn define_constant(&mut self, name: &Token, value: Arc<dyn Any + Send + Sync>) {
+        self.constants.insert(name.lexeme.clone(), name.line);
+        self.values.insert(name.lexeme.clone(), value);
+    }
+
+    /// Fails if `name` is already a constant in the current scope.
+    pub fn check_redeclare(&self, name: &Token) -> Result<(), RuntimeError> {
+        match self.constants.get(&name.lexeme) {
+            Some(line) => Err(RuntimeError::new(
+                name,
+                format!(
+                    "Cannot redeclare constant '{}' (declared on line {}, redeclared on line {}).",
+                    name.lexeme, line, name.line
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
 
     /// Retrieves the value of a variable.
     pub fn get(&self, name: &Token) -> Result<Arc<dyn Any + Send + Sync>, RuntimeError> {
@@ -75,6 +94,16 @@ impl Environment {
         name: &Token,
         value: Arc<dyn Any + Send + Sync>,
     ) -> Result<(), RuntimeError> {
+        if let Some(line) = self.constants.get(&name.lexeme) {
+            return Err(RuntimeError::new(
+                name,
+                format!(
+                    "Cannot assign to constant '{}' (declared on line {}, assigned on line {}).",
+                    name.lexeme, line, name.line
+                ),
+            ));
+        }
+
         if self.values.contains_key(&name.lexeme) {
             self.values.insert(name.lexeme.clone(), value);
             return Ok(());
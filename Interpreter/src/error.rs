@@ -1,14 +1,14 @@
 use crate::token::Token;
-#[warn(dead_code)]
 
-pub fn error(line: usize, message: &str, context: &str) {
-    eprintln!(
+/// An error found in the source, with the line it is on.
+pub fn format(line: usize, message: &str, context: &str) -> String {
+    format!(
         "[line {}] Error: {}\n{}\n{}^",
         line,
         message,
         context,
         " ".repeat(context.len())
-    );
+    )
 }
 
 
@@ -29,6 +29,6 @@ use std::fmt;
 
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Runtime error")
+        write!(f, "{}", self._message)
     }
 }
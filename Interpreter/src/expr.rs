@@ -1,8 +1,8 @@
 // Generated Rust AST for Expr
 use crate::token::Token;
 use std::any::Any;
-#[allow(dead_code)]
 
+#[allow(dead_code)]
 pub trait ExprVisitor {
     fn visit_binary(&self, expr: &Binary) -> String;
     fn visit_grouping(&self, expr: &Grouping) -> String;
@@ -21,7 +21,7 @@ pub enum Expr {
     Literal(Literal),
     Unary(Unary),
     Variable(Variable),
-    Assign(String, Box<Expr>), // Represents variable assignment
+    Assign(Token, Box<Expr>), // Represents variable assignment
     If {
         condition: Box<Expr>,
         then_branch: Box<Expr>,
@@ -43,11 +43,98 @@ pub enum Expr {
         object: Box<Expr>,
         index: Box<Expr>,
     },
+    SafeIndex {
+        object: Box<Expr>, // `object?[index]`: nil when object is nil or index is out of range
+        index: Box<Expr>,
+    },
     IndexAssign {
         object: Box<Expr>,
         index: Box<Expr>,
         value: Box<Expr>,
     },
+    Match {
+        subject: Box<Expr>,
+        arms: Vec<MatchArm<Expr>>,
+    },
+    ParallelAssign {
+        targets: Vec<Expr>, // Variables or index expressions
+        values: Vec<Expr>,  // One value per target, or a single array to unpack
+    },
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+        inclusive: bool, // `a..=b` includes `b`
+    },
+    Slice {
+        object: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+        step: Option<Box<Expr>>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token, // `Shape.Circle` or `circle.r`
+    },
+}
+
+// Patterns used by `match` arms
+#[derive(Clone)]
+pub enum Pattern {
+    Wildcard,                  // `_`
+    Literal(TokenLiteral),     // `1`, `"text"`, `true`, `nil`
+    Range {
+        start: f64,
+        end: f64,
+        inclusive: bool,       // `2..5` excludes 5, `2..=5` includes it
+    },
+    Binding(Token),            // `x` binds the matched value
+    Array {
+        elements: Vec<Pattern>,
+        rest: Option<Token>,   // `...rest` collects the remaining elements
+    },
+    Variant {
+        enum_name: Token,
+        variant: Token,
+        fields: Vec<Pattern>,  // `Shape.Circle(r)`; empty for `Shape.Empty`
+    },
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Literal(TokenLiteral::String(s)) => write!(f, "{:?}", s),
+            Pattern::Literal(TokenLiteral::Null) => write!(f, "nil"),
+            Pattern::Literal(TokenLiteral::Number(n)) => write!(f, "{}", n),
+            Pattern::Literal(literal) => write!(f, "{}", literal),
+            Pattern::Range { start, end, inclusive } => {
+                write!(f, "{}{}{}", start, if *inclusive { "..=" } else { ".." }, end)
+            }
+            Pattern::Binding(name) => write!(f, "{}", name.lexeme),
+            Pattern::Array { elements, rest } => {
+                let mut parts: Vec<String> = elements.iter().map(|p| p.to_string()).collect();
+                if let Some(rest) = rest {
+                    parts.push(format!("...{}", rest.lexeme));
+                }
+                write!(f, "[{}]", parts.join(", "))
+            }
+            Pattern::Variant { enum_name, variant, fields } if fields.is_empty() => {
+                write!(f, "{}.{}", enum_name.lexeme, variant.lexeme)
+            }
+            Pattern::Variant { enum_name, variant, fields } => {
+                let fields: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}.{}({})", enum_name.lexeme, variant.lexeme, fields.join(", "))
+            }
+        }
+    }
+}
+
+// A single `pattern if guard => body` arm; the body is a statement or an expression
+#[derive(Clone)]
+pub struct MatchArm<T> {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: T,
 }
 
 #[derive(Clone)]
@@ -111,67 +198,70 @@ impl Expr {
             Expr::Literal(l) => visitor.visit_literal(l),
             Expr::Unary(u) => visitor.visit_unary(u),
             Expr::Variable(v) => visitor.visit_variable(v),
-            Expr::Assign(name, _expr) => format!("Assign({}, ...)", name),
+            Expr::Assign(name, value) => format!("(= {} {})", name.lexeme, value.accept(visitor)),
             Expr::If {
                 condition,
                 then_branch,
                 else_branch,
-            } => {
-                format!(
-                    "If {{ {}, {}, {} }}",
+            } => match else_branch {
+                Some(else_branch) => format!(
+                    "(if {} {} {})",
                     condition.accept(visitor),
                     then_branch.accept(visitor),
-                    else_branch
-                        .as_ref()
-                        .map_or("None".to_string(), |e| e.accept(visitor))
-                )
-            }
+                    else_branch.accept(visitor)
+                ),
+                None => format!("(if {} {})", condition.accept(visitor), then_branch.accept(visitor)),
+            },
             Expr::Logical {
                 left,
                 operator,
                 right,
-            } => {
-                format!(
-                    "Logical {{ {}, {:?}, {} }}",
-                    left.accept(visitor),
-                    operator,
-                    right.accept(visitor)
-                )
-            }
+            } => format!("({} {} {})", operator.lexeme, left.accept(visitor), right.accept(visitor)),
             Expr::Call { callee, arguments } => {
-                format!(
-                    "Call {{ {}, {:?} }}",
-                    callee.accept(visitor),
-                    arguments
-                        .iter()
-                        .map(|e| e.accept(visitor))
-                        .collect::<Vec<String>>()
-                )
+                let mut parts = vec![callee.accept(visitor)];
+                parts.extend(arguments.iter().map(|e| e.accept(visitor)));
+                format!("(call {})", parts.join(" "))
             }
             Expr::Array { elements } => {
-                format!(
-                    "Array {{ {:?} }}",
-                    elements
-                        .iter()
-                        .map(|e| e.accept(visitor))
-                        .collect::<Vec<String>>()
-                )
+                let elements: Vec<String> = elements.iter().map(|e| e.accept(visitor)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Expr::Index { object, index } => format!("(index {} {})", object.accept(visitor), index.accept(visitor)),
+            Expr::SafeIndex { object, index } => {
+                format!("(?index {} {})", object.accept(visitor), index.accept(visitor))
+            }
+            Expr::ParallelAssign { targets, values } => {
+                let targets: Vec<String> = targets.iter().map(|t| t.accept(visitor)).collect();
+                let values: Vec<String> = values.iter().map(|v| v.accept(visitor)).collect();
+                format!("(= [{}] [{}])", targets.join(", "), values.join(", "))
             }
-            Expr::Index { object, index } => {
-                format!(
-                    "Index {{ {}, {} }}",
-                    object.accept(visitor),
-                    index.accept(visitor)
-                )
+            Expr::IndexAssign { object, index, value } => format!(
+                "(index= {} {} {})",
+                object.accept(visitor),
+                index.accept(visitor),
+                value.accept(visitor)
+            ),
+            Expr::Match { subject, arms } => {
+                let arms: Vec<String> = arms
+                    .iter()
+                    .map(|arm| match &arm.guard {
+                        Some(guard) => format!("({} if {} {})", arm.pattern, guard.accept(visitor), arm.body.accept(visitor)),
+                        None => format!("({} {})", arm.pattern, arm.body.accept(visitor)),
+                    })
+                    .collect();
+                format!("(match {} {})", subject.accept(visitor), arms.join(" "))
             }
-            Expr::IndexAssign { object, index, value } => {
-                format!(
-                    "IndexAssign {{ {}, {}, {} }}",
-                    object.accept(visitor),
-                    index.accept(visitor),
-                    value.accept(visitor)
-                )
+            Expr::Range { start, end, inclusive } => format!(
+                "({} {} {})",
+                if *inclusive { "..=" } else { ".." },
+                start.accept(visitor),
+                end.accept(visitor)
+            ),
+            Expr::Slice { object, start, end, step } => {
+                let part = |e: &Option<Box<Expr>>| e.as_ref().map_or("_".to_string(), |e| e.accept(visitor));
+                format!("(slice {} {} {} {})", object.accept(visitor), part(start), part(end), part(step))
             }
+            Expr::Get { object, name } => format!("(. {} {})", object.accept(visitor), name.lexeme),
         }
     }
 }
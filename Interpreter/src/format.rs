@@ -0,0 +1,342 @@
+use crate::interpreter::Interpreter;
+use crate::number;
+use num_bigint::BigInt;
+use rust_decimal::Decimal;
+use std::any::Any;
+use std::sync::Arc;
+
+// Formatting engine behind `format()` and `write`. Templates follow Rust's
+// syntax: `{}` takes the next argument, `{1}` a numbered one and `{name}` a
+// variable in scope. A spec after a colon controls the layout, e.g. `{:>8.2}`:
+//
+//   [[fill]align][+][#][0][width][.precision][type]
+//
+// align is `<`, `>` or `^`; type is `x`, `X`, `b`, `o` or `e`.
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+// Largest width and precision a spec may ask for; beyond them one placeholder
+// could exhaust memory or overflow the formatter
+const MAX_WIDTH: usize = 1000;
+const MAX_PRECISION: usize = 1000;
+
+#[derive(Default)]
+pub(crate) struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    sign: bool,      // `+` shows the sign of positive numbers too
+    alternate: bool, // `#` adds a `0x`, `0b` or `0o` prefix
+    zero: bool,      // `0` pads numbers with zeros after the sign
+    width: usize,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+/// Fills in the placeholders of `template`.
+pub(crate) fn format_template(interpreter: &Interpreter, template: &str, arguments: &[Value]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+    let mut next_argument = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '}' => return Err("Unmatched '}' in format string; write '}}' for a literal brace.".to_string()),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err("Unclosed '{' in format string.".to_string()),
+                    }
+                }
+
+                let (reference, spec) = match placeholder.split_once(':') {
+                    Some((reference, spec)) => (reference.trim(), parse_spec(spec)?),
+                    None => (placeholder.trim(), Spec::default()),
+                };
+
+                let value = if reference.is_empty() {
+                    next_argument += 1;
+                    argument(arguments, next_argument - 1)?
+                } else if let Ok(position) = reference.parse::<usize>() {
+                    argument(arguments, position)?
+                } else {
+                    interpreter
+                        .variable(reference)
+                        .ok_or_else(|| format!("Undefined variable '{}' in format string.", reference))?
+                };
+                output.push_str(&format_value(interpreter, &value, &spec)?);
+            }
+            c => output.push(c),
+        }
+    }
+    Ok(output)
+}
+
+/// The variables `template` refers to by name, like `total` in `{total:>8}`.
+pub(crate) fn named_placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix('{') {
+            rest = escaped;
+            continue;
+        }
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let placeholder = &after[..end];
+        let reference = placeholder.split_once(':').map_or(placeholder, |(reference, _)| reference).trim();
+        if !reference.is_empty() && reference.parse::<usize>().is_err() {
+            names.push(reference);
+        }
+        rest = &after[end + 1..];
+    }
+    names
+}
+
+fn argument(arguments: &[Value], position: usize) -> Result<Value, String> {
+    arguments.get(position).cloned().ok_or_else(|| {
+        format!(
+            "Format string refers to argument {} but only {} were given.",
+            position,
+            arguments.len()
+        )
+    })
+}
+
+fn parse_spec(text: &str) -> Result<Spec, String> {
+    let invalid = || format!("Invalid format spec '{}'.", text);
+    let chars: Vec<char> = text.chars().collect();
+    let mut spec = Spec::default();
+    let mut i = 0;
+
+    let is_align = |c: Option<&char>| matches!(c, Some('<' | '>' | '^'));
+    if is_align(chars.get(1)) {
+        spec.fill = Some(chars[0]);
+        spec.align = Some(chars[1]);
+        i = 2;
+    } else if is_align(chars.first()) {
+        spec.align = Some(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'+') {
+        spec.sign = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'#') {
+        spec.alternate = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        spec.zero = true;
+        i += 1;
+    }
+
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse::<usize>().ok()
+    };
+    let start = i;
+    let width = digits(&mut i);
+    if i > start {
+        match width {
+            Some(width) if width <= MAX_WIDTH => spec.width = width,
+            _ => return Err(format!("Format width in '{}' is larger than {}.", text, MAX_WIDTH)),
+        }
+    }
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        let start = i;
+        let precision = digits(&mut i);
+        if i == start {
+            return Err(invalid());
+        }
+        match precision {
+            Some(precision) if precision <= MAX_PRECISION => spec.precision = Some(precision),
+            _ => return Err(format!("Format precision in '{}' is larger than {}.", text, MAX_PRECISION)),
+        }
+    }
+
+    match chars.get(i) {
+        Some(kind @ ('x' | 'X' | 'b' | 'o' | 'e')) => {
+            spec.kind = Some(*kind);
+            i += 1;
+        }
+        Some(_) => return Err(invalid()),
+        None => {}
+    }
+    if i != chars.len() {
+        return Err(invalid());
+    }
+    Ok(spec)
+}
+
+/// Formats one value; an empty spec gives the same text as `str(value)`.
+pub(crate) fn format_value(interpreter: &Interpreter, value: &Value, spec: &Spec) -> Result<String, String> {
+    if !number::is_number(value) {
+        if spec.kind.is_some() || spec.sign || spec.zero {
+            return Err(format!(
+                "Format spec needs a number but got '{}'.",
+                interpreter.stringify(value)
+            ));
+        }
+        let mut text = interpreter.stringify(value);
+        if let Some(precision) = spec.precision {
+            text = text.chars().take(precision).collect(); // Precision truncates text
+        }
+        return Ok(pad(text, String::new(), spec, '<'));
+    }
+
+    let (negative, digits) = number_digits(interpreter, value, spec)?;
+    let mut prefix = if negative {
+        "-".to_string()
+    } else if spec.sign {
+        "+".to_string()
+    } else {
+        String::new()
+    };
+    if spec.alternate {
+        prefix.push_str(match spec.kind {
+            Some('x' | 'X') => "0x",
+            Some('b') => "0b",
+            Some('o') => "0o",
+            _ => "",
+        });
+    }
+    Ok(pad(digits, prefix, spec, '>'))
+}
+
+// The digits of a number without its sign
+fn number_digits(interpreter: &Interpreter, value: &Value, spec: &Spec) -> Result<(bool, String), String> {
+    if let Some(radix) = spec.kind.and_then(|kind| match kind {
+        'x' | 'X' => Some(16),
+        'b' => Some(2),
+        'o' => Some(8),
+        _ => None,
+    }) {
+        let n = number::as_integer(value)
+            .ok_or_else(|| format!("Format type '{}' needs a whole number.", spec.kind.unwrap_or('x')))?;
+        let mut digits = n.magnitude().to_str_radix(radix);
+        if spec.kind == Some('X') {
+            digits = digits.to_uppercase();
+        }
+        return Ok((n.sign() == num_bigint::Sign::Minus, digits));
+    }
+
+    let text = if spec.kind == Some('e') {
+        let n = number::as_float(value).unwrap_or(f64::NAN);
+        match spec.precision {
+            Some(precision) => format!("{:.*e}", precision, n),
+            None => format!("{:e}", n),
+        }
+    } else if let Some(precision) = spec.precision {
+        if let Some(d) = value.downcast_ref::<Decimal>() {
+            format!("{:.*}", precision, d)
+        } else if let Some(n) = value.downcast_ref::<BigInt>() {
+            format!("{}{}{}", n, if precision > 0 { "." } else { "" }, "0".repeat(precision))
+        } else {
+            format!("{:.*}", precision, number::as_float(value).unwrap_or(f64::NAN))
+        }
+    } else {
+        interpreter.stringify(value)
+    };
+
+    match text.strip_prefix('-') {
+        Some(digits) => Ok((true, digits.to_string())),
+        None => Ok((false, text)),
+    }
+}
+
+// Pads to the spec's width; `default_align` is `<` for text and `>` for numbers
+fn pad(body: String, prefix: String, spec: &Spec, default_align: char) -> String {
+    let length = prefix.chars().count() + body.chars().count();
+    if length >= spec.width {
+        return prefix + &body;
+    }
+    let missing = spec.width - length;
+
+    // Zero padding goes between the sign and the digits
+    if spec.zero && spec.align.is_none() {
+        return prefix + &"0".repeat(missing) + &body;
+    }
+
+    let fill = spec.fill.unwrap_or(' ').to_string();
+    let (before, after) = match spec.align.unwrap_or(default_align) {
+        '<' => (0, missing),
+        '^' => (missing / 2, missing - missing / 2),
+        _ => (missing, 0),
+    };
+    fill.repeat(before) + &prefix + &body + &fill.repeat(after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(template: &str, arguments: &[Value]) -> Result<String, String> {
+        format_template(&Interpreter::new(), template, arguments)
+    }
+
+    fn number(n: f64) -> Value {
+        Arc::new(n)
+    }
+
+    #[test]
+    fn placeholders_take_arguments_in_order_or_by_position() {
+        let arguments = [number(1.0), Arc::new("two".to_string()) as Value];
+        assert_eq!(format("{} {}", &arguments).unwrap(), "1 two");
+        assert_eq!(format("{1} {0} {1}", &arguments).unwrap(), "two 1 two");
+        assert_eq!(format("{{}} {}", &arguments).unwrap(), "{} 1");
+        assert!(format("{2}", &arguments).is_err());
+        assert!(format("{", &arguments).is_err());
+        assert!(format("}", &arguments).is_err());
+    }
+
+    #[test]
+    fn specs_control_the_layout() {
+        assert_eq!(format("[{:>6.2}]", &[number(1.23456)]).unwrap(), "[  1.23]");
+        assert_eq!(format("[{:*^7}]", &[number(42.0)]).unwrap(), "[**42***]");
+        assert_eq!(format("{:+08.1}", &[number(-2.5)]).unwrap(), "-00002.5");
+        assert_eq!(format("{:#x} {:b} {:o}", &[number(255.0), number(5.0), number(8.0)]).unwrap(), "0xff 101 10");
+        assert_eq!(format("{:.1e}", &[number(1234.0)]).unwrap(), "1.2e3");
+    }
+
+    #[test]
+    fn width_and_precision_are_capped() {
+        assert_eq!(parse_spec("1000").unwrap().width, MAX_WIDTH);
+        assert_eq!(parse_spec(".1000").unwrap().precision, Some(MAX_PRECISION));
+        assert_eq!(
+            parse_spec("1001").err().as_deref(),
+            Some("Format width in '1001' is larger than 1000.")
+        );
+        assert_eq!(
+            parse_spec(".1001e").err().as_deref(),
+            Some("Format precision in '.1001e' is larger than 1000.")
+        );
+        // Too many digits for a usize is still just too large
+        assert!(parse_spec("99999999999999999999999").is_err());
+        assert!(parse_spec(".").is_err());
+        assert!(parse_spec("5q").is_err());
+    }
+
+    #[test]
+    fn named_placeholders_are_found_outside_escapes() {
+        assert_eq!(named_placeholders("{total:>8} {0} {} {name}"), ["total", "name"]);
+        assert_eq!(named_placeholders("{{skipped}} { spaced }"), ["spaced"]);
+        assert!(named_placeholders("{unclosed").is_empty());
+    }
+}
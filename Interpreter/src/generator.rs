@@ -0,0 +1,302 @@
+use crate::environment::Environment;
+use crate::expr::{Expr, MatchArm};
+use crate::interpreter::AoiIterator;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Generator bodies run on the thread that resumes them. A body is flattened
+// into steps once: statements without a `yield` inside run in one go, and the
+// ones around a `yield` become jumps and scopes. A generator can then stop
+// after any `yield` with nothing of it left on the Rust stack.
+
+pub(crate) enum Step {
+    Run(Stmt), // Has no `yield` inside
+    Yield(Option<Expr>),
+    Enter, // Opens a scope nested in the current one
+    Leave, // Closes the scope opened last
+    Jump(usize),
+    JumpUnless(Expr, usize), // Jumps when the condition is false
+    Iterate(Expr),           // Starts a `for-in` over the value
+    // Opens a scope holding the next item, or ends the innermost `for-in` and jumps
+    Next {
+        index: Option<Token>,
+        variable: Token,
+        done: usize,
+    },
+    // Opens the scope of the selected arm and jumps to the arm's first step
+    Match {
+        subject: Expr,
+        arms: Vec<MatchArm<usize>>,
+    },
+}
+
+/// Flattens a generator body into the steps `Interpreter::resume_generator` runs.
+pub(crate) fn compile(body: &[Stmt]) -> Vec<Step> {
+    let mut steps = Vec::new();
+    for stmt in body {
+        compile_stmt(stmt, &mut steps);
+    }
+    steps
+}
+
+fn compile_stmt(stmt: &Stmt, steps: &mut Vec<Step>) {
+    if !contains_yield(stmt) {
+        steps.push(Step::Run(stmt.clone()));
+        return;
+    }
+
+    match stmt {
+        Stmt::Yield { value, .. } => steps.push(Step::Yield(value.clone())),
+        Stmt::Block(statements) => {
+            steps.push(Step::Enter);
+            for stmt in statements {
+                compile_stmt(stmt, steps);
+            }
+            steps.push(Step::Leave);
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            let test = steps.len();
+            steps.push(Step::JumpUnless(condition.clone(), 0));
+            compile_stmt(then_branch, steps);
+            if let Some(else_branch) = else_branch {
+                let skip = steps.len();
+                steps.push(Step::Jump(0));
+                patch(steps, test);
+                compile_stmt(else_branch, steps);
+                patch(steps, skip);
+            } else {
+                patch(steps, test);
+            }
+        }
+        Stmt::While { condition, body, .. } => {
+            let test = steps.len();
+            steps.push(Step::JumpUnless(condition.clone(), 0));
+            compile_stmt(body, steps);
+            steps.push(Step::Jump(test));
+            patch(steps, test);
+        }
+        Stmt::For {
+            initializer,
+            condition,
+            increment,
+            body,
+        } => {
+            if let Some(initializer) = initializer {
+                compile_stmt(initializer, steps);
+            }
+            let start = steps.len();
+            if let Some(condition) = condition {
+                steps.push(Step::JumpUnless(condition.clone(), 0));
+            }
+            compile_stmt(body, steps);
+            if let Some(increment) = increment {
+                steps.push(Step::Run(Stmt::Expression {
+                    expression: increment.clone(),
+                }));
+            }
+            steps.push(Step::Jump(start));
+            if condition.is_some() {
+                patch(steps, start);
+            }
+        }
+        Stmt::ForIn {
+            index,
+            variable,
+            iterable,
+            body,
+        } => {
+            steps.push(Step::Iterate(iterable.clone()));
+            let next = steps.len();
+            steps.push(Step::Next {
+                index: index.clone(),
+                variable: variable.clone(),
+                done: 0,
+            });
+            compile_stmt(body, steps);
+            steps.push(Step::Leave);
+            steps.push(Step::Jump(next));
+            patch(steps, next);
+        }
+        Stmt::Match { subject, arms } => {
+            let select = steps.len();
+            steps.push(Step::Match {
+                subject: subject.clone(),
+                arms: Vec::new(),
+            });
+            let mut compiled = Vec::new();
+            let mut exits = Vec::new();
+            for arm in arms {
+                compiled.push(MatchArm {
+                    pattern: arm.pattern.clone(),
+                    guard: arm.guard.clone(),
+                    body: steps.len(),
+                });
+                compile_stmt(&arm.body, steps);
+                steps.push(Step::Leave);
+                exits.push(steps.len());
+                steps.push(Step::Jump(0));
+            }
+            for exit in exits {
+                patch(steps, exit);
+            }
+            if let Step::Match { arms, .. } = &mut steps[select] {
+                *arms = compiled;
+            }
+        }
+        _ => unreachable!("contains_yield only looks inside the statements above"),
+    }
+}
+
+// Points the jump at `at` to the step after the last one
+fn patch(steps: &mut [Step], at: usize) {
+    let end = steps.len();
+    match &mut steps[at] {
+        Step::Jump(target) | Step::JumpUnless(_, target) | Step::Next { done: target, .. } => *target = end,
+        _ => unreachable!("only jumps are patched"),
+    }
+}
+
+// Whether the statement can stop at a `yield` of this function. Nested
+// functions yield for themselves, and a `yield` in a `test` block fails when run.
+fn contains_yield(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Yield { .. } => true,
+        Stmt::Block(statements) => statements.iter().any(contains_yield),
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => contains_yield(then_branch) || else_branch.as_deref().is_some_and(contains_yield),
+        Stmt::While { body, .. } | Stmt::ForIn { body, .. } => contains_yield(body),
+        Stmt::For { initializer, body, .. } => {
+            initializer.as_deref().is_some_and(contains_yield) || contains_yield(body)
+        }
+        Stmt::Match { arms, .. } => arms.iter().any(|arm| contains_yield(&arm.body)),
+        _ => false,
+    }
+}
+
+// Returned by calling a function whose body contains `yield`. `state` is
+// borrowed while the body runs and is `None` once it has finished.
+pub(crate) struct Generator {
+    pub(crate) name: String,
+    pub(crate) state: RefCell<Option<GeneratorState>>,
+}
+
+// Make Generator Send + Sync, like the `Rc`-based values it holds
+unsafe impl Send for Generator {}
+unsafe impl Sync for Generator {}
+
+// Where a suspended generator body stands
+pub(crate) struct GeneratorState {
+    pub(crate) steps: Rc<Vec<Step>>,
+    pub(crate) next: usize, // Index of the step to run next
+    pub(crate) environment: Rc<RefCell<Environment>>,
+    pub(crate) scopes: Vec<Rc<RefCell<Environment>>>, // Environments to return to on `Leave`
+    pub(crate) iterators: Vec<(Box<dyn AoiIterator>, usize)>, // Running `for-in` loops and their counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::token::Tokensizer;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Tokensizer::new(source.to_string()).tokenize().expect("tokenizes");
+        Parser::new(tokens).parse().expect("parses")
+    }
+
+    // The steps of the first function in `source`
+    fn steps(source: &str) -> Vec<Step> {
+        match &parse(source)[0] {
+            Stmt::Function { body, .. } => compile(body),
+            _ => panic!("expected a function"),
+        }
+    }
+
+    // One letter per step, e.g. "RYJ" for Run, Yield, Jump
+    fn shape(steps: &[Step]) -> String {
+        steps
+            .iter()
+            .map(|step| match step {
+                Step::Run(_) => 'R',
+                Step::Yield(_) => 'Y',
+                Step::Enter => '{',
+                Step::Leave => '}',
+                Step::Jump(_) => 'J',
+                Step::JumpUnless(..) => 'U',
+                Step::Iterate(_) => 'I',
+                Step::Next { .. } => 'N',
+                Step::Match { .. } => 'M',
+            })
+            .collect()
+    }
+
+    fn run(source: &str) -> String {
+        Interpreter::new().interpret(&parse(source))
+    }
+
+    #[test]
+    fn statements_without_yield_run_in_one_step() {
+        let steps = steps("fun f() { var a = 1; while (a < 3) { a = a + 1; } yield a; }");
+        assert_eq!(shape(&steps), "RRY");
+    }
+
+    #[test]
+    fn loops_around_a_yield_become_jumps() {
+        let looped = steps("fun f(n) { while (n > 0) { yield n; n = n - 1; } }");
+        assert_eq!(shape(&looped), "U{YR}J");
+        assert!(matches!(looped[0], Step::JumpUnless(_, 6)), "the test jumps past the loop");
+        assert!(matches!(looped[5], Step::Jump(0)), "the loop jumps back to the test");
+
+        let each = steps("fun f(items) { for (x in items) { yield x; } }");
+        assert_eq!(shape(&each), "IN{Y}}J");
+        assert!(matches!(each[1], Step::Next { done: 7, .. }));
+    }
+
+    #[test]
+    fn if_else_jumps_over_the_other_branch() {
+        let steps = steps("fun f(a) { if (a) yield 1; else yield 2; }");
+        assert_eq!(shape(&steps), "UYJY");
+        assert!(matches!(steps[0], Step::JumpUnless(_, 3)));
+        assert!(matches!(steps[2], Step::Jump(4)));
+    }
+
+    #[test]
+    fn yields_of_nested_functions_belong_to_them() {
+        let steps = steps("fun f() { fun inner() { yield 1; } yield inner; }");
+        assert_eq!(shape(&steps), "RY");
+    }
+
+    #[test]
+    fn each_resume_runs_to_the_next_yield() {
+        let output = run(
+            "fun f() { write(\"start\"); var i = 0; while (i < 2) { yield i; write(\"after \" + str(i)); i = i + 1; } }
+             var g = f();
+             write(\"created\");
+             write(next(g));
+             write(next(g));
+             write(next(g));
+             write(next(g));",
+        );
+        assert_eq!(output, "created\nstart\n0\nafter 0\n1\nafter 1\nnil\nnil\n");
+    }
+
+    #[test]
+    fn for_in_over_a_generator_keeps_scopes_apart() {
+        let output = run(
+            "fun pairs(items) { for (i, x in items) { var doubled = x * 2; yield [i, doubled]; } }
+             for (pair in pairs([5, 6])) { write(pair); }",
+        );
+        assert_eq!(output, "[0, 10]\n[1, 12]\n");
+    }
+}
@@ -1,20 +1,31 @@
+use crate::builtins::{self, NativeFunction};
+use crate::coroutine::{Coroutine, Link, Registry, Step, STACK_SIZE};
+use crate::docgen;
 use crate::environment::{self, Environment};
-use crate::expr::Expr;
-use crate::stmt::Stmt;
+use crate::expr::{Expr, MatchArm, Pattern};
+use crate::format;
+use crate::generator::{self, Generator, GeneratorState};
+use crate::number;
+use crate::stmt::{EnumVariant, Stmt};
+use crate::tasks::{Attempt, Channel, Scheduler, Task, MAX_TASKS};
 use crate::token::{Token, TokenLiteral, TokenType};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+use rust_decimal::Decimal;
 use std::any::Any;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 // Wrapper for mutable array that we can use with Arc
 #[derive(Clone)]
-struct AoiArray {
-    elements: Rc<RefCell<Vec<Arc<dyn Any + Send + Sync>>>>,
+pub(crate) struct AoiArray {
+    pub(crate) elements: Rc<RefCell<Vec<Arc<dyn Any + Send + Sync>>>>,
 }
 
 impl AoiArray {
-    fn new(elements: Vec<Arc<dyn Any + Send + Sync>>) -> Self {
+    pub(crate) fn new(elements: Vec<Arc<dyn Any + Send + Sync>>) -> Self {
         AoiArray {
             elements: Rc::new(RefCell::new(elements)),
         }
@@ -34,7 +45,7 @@ impl AoiArray {
         }
     }
 
-    fn len(&self) -> usize {
+    pub(crate) fn len(&self) -> usize {
         self.elements.borrow().len()
     }
 }
@@ -43,13 +54,139 @@ impl AoiArray {
 unsafe impl Send for AoiArray {}
 unsafe impl Sync for AoiArray {}
 
+// Integer range produced by `start..end` or `start..=end`
+#[derive(Clone)]
+struct AoiRange {
+    start: i64,
+    end: i64,
+    inclusive: bool,
+}
+
+impl AoiRange {
+    // First value past the end of the range
+    fn exclusive_end(&self) -> i64 {
+        if self.inclusive {
+            self.end + 1
+        } else {
+            self.end
+        }
+    }
+}
+
+// An `enum` declaration; `Shape.Circle` looks up its variants
+struct AoiEnum {
+    name: String,
+    variants: Vec<EnumVariant>,
+}
+
+// `Shape.Circle` for a variant with fields; calling it builds the value
+#[derive(Clone)]
+struct EnumConstructor {
+    enum_name: String,
+    variant: String,
+    fields: Vec<String>,
+}
+
+// A tagged value such as `Shape.Circle(2)`. Immutable, so copies share it.
+pub(crate) struct EnumValue {
+    enum_name: String,
+    pub(crate) variant: String,
+    fields: Vec<String>,
+    pub(crate) payload: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+// Iteration protocol behind `for (x in ...)`. A container type becomes
+// iterable by returning one of these from `Interpreter::iterate`.
+pub(crate) trait AoiIterator {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Arc<dyn Any + Send + Sync>>, String>;
+}
+
+// Walks the live array, so elements changed during the loop are seen
+struct ArrayIterator {
+    array: AoiArray,
+    position: usize,
+}
+
+impl AoiIterator for ArrayIterator {
+    fn next(&mut self, _interpreter: &mut Interpreter) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let item = self.array.get(self.position);
+        self.position += 1;
+        Ok(item)
+    }
+}
+
+// Yields each character as a one-character string
+struct StringIterator {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl AoiIterator for StringIterator {
+    fn next(&mut self, _interpreter: &mut Interpreter) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let item = self.chars.get(self.position).map(|c| Arc::new(c.to_string()) as Arc<dyn Any + Send + Sync>);
+        self.position += 1;
+        Ok(item)
+    }
+}
+
+struct RangeIterator {
+    current: i64,
+    end: i64, // Exclusive
+}
+
+impl AoiIterator for RangeIterator {
+    fn next(&mut self, _interpreter: &mut Interpreter) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        if self.current >= self.end {
+            return Ok(None);
+        }
+        let item = Arc::new(self.current as f64);
+        self.current += 1;
+        Ok(Some(item))
+    }
+}
+
+// Pulls values from a suspended generator function
+struct GeneratorIterator {
+    generator: Arc<dyn Any + Send + Sync>,
+}
+
+impl AoiIterator for GeneratorIterator {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let generator = self.generator.downcast_ref::<Generator>().expect("generator iterator");
+        interpreter.resume_generator(generator)
+    }
+}
+
+// Receives from a channel until it is closed and drained
+struct ChannelIterator {
+    channel: Arc<dyn Any + Send + Sync>,
+}
+
+impl AoiIterator for ChannelIterator {
+    fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let channel = self.channel.downcast_ref::<Channel>().expect("channel iterator");
+        interpreter.receive(channel)
+    }
+}
+
+// A built-in taken from a value, such as `gen.next`; calling it passes the value first
+#[derive(Clone)]
+pub(crate) struct BoundMethod {
+    receiver: Arc<dyn Any + Send + Sync>,
+    method: NativeFunction,
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 struct Function {
     name: String,
     params: Vec<Token>,
-    body: Vec<Box<Stmt>>,
+    body: Vec<Stmt>,
     closure: Rc<RefCell<Environment>>, // Captures the defining environment
+    generator: bool,                   // Calls return a `Generator` instead of running the body
+    steps: Option<Rc<Vec<generator::Step>>>, // The body flattened, for generators
+    signature: String,                 // `fun area(w: num, h: num): num`, for `help()`
+    doc: Option<String>,
 }
 
 // Implement Send and Sync for Function to satisfy Arc<dyn Any + Send + Sync>
@@ -60,175 +197,1340 @@ impl Function {
     fn new(
         name: String,
         params: Vec<Token>,
-        body: Vec<Box<Stmt>>,
+        body: Vec<Stmt>,
         closure: Rc<RefCell<Environment>>,
+        generator: bool,
+        signature: String,
+        doc: Option<String>,
     ) -> Self {
+        let steps = generator.then(|| Rc::new(generator::compile(&body)));
         Function {
             name,
             params,
             body,
             closure,
+            generator,
+            steps,
+            signature,
+            doc,
         }
     }
 }
+// Error message used to unwind from `return` up to the enclosing call
+const RETURN_SIGNAL: &str = "Return:";
+
+// Error message used by `exit(code)` to unwind the whole program, followed by the code
+pub(crate) const EXIT_SIGNAL: &str = "Exit:";
+
+// The code `exit(code)` asked for, when `error` is its signal
+fn exit_request(error: &str) -> Option<i32> {
+    let rest = error.strip_prefix(EXIT_SIGNAL)?;
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+// A call made by `return f(...)`, left for `run_body` to make in place of the caller
+struct TailCall {
+    callee: Arc<dyn Any + Send + Sync>,
+    arguments: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+/// Outcome of one `test "name" { ... }` block.
+pub struct TestResult {
+    pub name: String,
+    pub error: Option<String>, // Why it failed
+}
+
+// Deepest nesting of Aoi calls before a "stack overflow" error
+const MAX_CALL_DEPTH: usize = 100_000;
+
+// Stack kept free below the deepest call for the frames that call still needs
+const STACK_RESERVE: usize = 8 * 1024 * 1024;
+
+// Deepest nesting `==` and `<` follow into arrays and enum values, so an array
+// that contains itself gives an error instead of overflowing the stack
+const MAX_COMPARE_DEPTH: usize = 10_000;
+
+fn too_deep_to_compare() -> String {
+    format!(
+        "Cannot compare values nested more than {} levels deep; does an array contain itself?",
+        MAX_COMPARE_DEPTH
+    )
+}
+
+// Address of a local variable, i.e. how far down its stack this thread is
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0_u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// What a task's thread needs to build its own interpreter
+struct CoroutineSeed {
+    environment: Rc<RefCell<Environment>>,
+    coroutines: Registry,
+    scheduler: Arc<Scheduler>,
+    strict: bool,
+    sandboxed: bool,
+}
+
+impl CoroutineSeed {
+    fn grow(self, link: Link) -> Interpreter {
+        Interpreter {
+            environment: self.environment,
+            output: String::new(),
+            return_value: None,
+            coroutine: Some(link),
+            coroutines: self.coroutines,
+            scheduler: self.scheduler,
+            in_task: true,
+            strict: self.strict,
+            sandboxed: self.sandboxed,
+            exit_code: None,
+            failed: false,
+            test_results: None,
+            call_stack: Vec::new(),
+            stack_start: stack_address(),
+            tail_call: None,
+        }
+    }
+}
+
 pub struct Interpreter {
     environment: Rc<RefCell<Environment>>,
     output: String,
+    return_value: Option<Arc<dyn Any + Send + Sync>>, // Set by `return` while unwinding
+    coroutine: Option<Link>,   // Set when running a task body
+    coroutines: Registry,      // Shared with every task started by this run
+    scheduler: Arc<Scheduler>, // Shared with every task of this run
+    in_task: bool,             // Waiting hands control back to the scheduler
+    strict: bool,              // Conditions must be booleans
+    sandboxed: bool,           // Running for the server: no environment variables or stderr
+    exit_code: Option<i32>,    // Set by `exit(code)`
+    failed: bool,              // A runtime error was reported
+    test_results: Option<Vec<TestResult>>, // Set in test mode, where `test` blocks run
+    call_stack: Vec<String>,   // Names of the functions being run, innermost last
+    stack_start: usize,        // `stack_address()` when this interpreter's thread began
+    tail_call: Option<TailCall>,
 }
 impl Interpreter {
     pub fn new() -> Self {
+        let mut globals = environment::Environment::new(None);
+        builtins::define_globals(&mut globals);
         Interpreter {
-            environment: Rc::new(RefCell::new(environment::Environment::new(None))),
+            environment: Rc::new(RefCell::new(globals)),
             output: String::new(),
+            return_value: None,
+            coroutine: None,
+            coroutines: Registry::default(),
+            scheduler: Arc::new(Scheduler::new(false)),
+            in_task: false,
+            strict: false,
+            sandboxed: false,
+            exit_code: None,
+            failed: false,
+            test_results: None,
+            call_stack: Vec::new(),
+            stack_start: stack_address(),
+            tail_call: None,
+        }
+    }
+
+    /// Runs tasks in spawn order instead of a random order, so output is reproducible.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.scheduler = Arc::new(Scheduler::new(deterministic));
+    }
+
+    /// Makes every condition, and the operands of `!`, `and` and `or`, require a boolean.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Shuts the program off from the machine it runs on: `env()` fails and
+    /// `ewrite` output joins the regular output.
+    pub fn set_sandboxed(&mut self, sandboxed: bool) {
+        self.sandboxed = sandboxed;
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub(crate) fn is_sandboxed(&self) -> bool {
+        self.sandboxed
+    }
+
+    /// Makes the command-line arguments after the file name available as `args`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let args = args.into_iter().map(|arg| Arc::new(arg) as Arc<dyn Any + Send + Sync>).collect();
+        self.environment
+            .borrow_mut()
+            .define("args".to_string(), Arc::new(AoiArray::new(args)));
+    }
+
+    /// Runs `test` blocks, which are skipped otherwise.
+    pub fn set_test_mode(&mut self, test_mode: bool) {
+        self.test_results = test_mode.then(Vec::new);
+    }
+
+    /// Results of the `test` blocks run so far, in order.
+    pub fn test_results(&self) -> &[TestResult] {
+        self.test_results.as_deref().unwrap_or_default()
+    }
+
+    /// Exit status for the process: the code given to `exit`, otherwise 1 after
+    /// a runtime error and 0 after a clean run.
+    pub fn exit_status(&self) -> i32 {
+        self.exit_code.unwrap_or(if self.failed { 1 } else { 0 })
+    }
+
+    // The parts of this interpreter a task body shares, to be moved to its thread
+    fn for_coroutine(&self, environment: Rc<RefCell<Environment>>) -> CoroutineSeed {
+        CoroutineSeed {
+            environment,
+            coroutines: self.coroutines.clone(),
+            scheduler: self.scheduler.clone(),
+            strict: self.strict,
+            sandboxed: self.sandboxed,
+        }
+    }
+
+
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> String {
+        for statement in statements {
+            if let Err(err) = self.visit_stmt(statement) {
+                if let Some(code) = exit_request(&err) {
+                    self.exit_code = Some(code);
+                } else {
+                    self.output.push_str(&format!("Runtime error: {}\n", err));
+                    self.failed = true;
+                }
+            }
+            if self.exit_code.is_some() {
+                break; // Set here or by a task
+            }
+        }
+        // Tasks that can still make progress get to finish
+        while self.exit_code.is_none() && self.run_tasks() {}
+        self.report_tasks();
+        self.coroutines.shutdown();
+        self.output.clone()
+    }
+    
+    
+    
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), String> {
+        self.visit_stmt(stmt)
+    }
+    // The one truthiness rule: `false`, `nil`, zero, `""` and `[]` are false and
+    // every other value is true
+    pub(crate) fn is_truthy(&self, value: &Arc<dyn Any + Send + Sync>) -> bool {
+        if let Some(b) = value.downcast_ref::<bool>() {
+            *b
+        } else if let Some(n) = value.downcast_ref::<f64>() {
+            *n != 0.0
+        } else if let Some(n) = value.downcast_ref::<i64>() {
+            *n != 0 // What a function without `return` gives back
+        } else if let Some(n) = value.downcast_ref::<BigInt>() {
+            !n.is_zero()
+        } else if let Some(n) = value.downcast_ref::<Decimal>() {
+            !n.is_zero()
+        } else if let Some(s) = value.downcast_ref::<String>() {
+            !s.is_empty()
+        } else if let Some(array) = value.downcast_ref::<AoiArray>() {
+            !array.elements.borrow().is_empty()
+        } else {
+            !self.is_nil(value)
+        }
+    }
+
+    // Reads a value used as a condition, which strict mode requires to be a boolean
+    pub(crate) fn condition(&self, value: &Arc<dyn Any + Send + Sync>) -> Result<bool, String> {
+        match value.downcast_ref::<bool>() {
+            Some(b) => Ok(*b),
+            None if self.strict => Err(format!(
+                "Condition must be a boolean but got {} '{}'.",
+                self.type_name(value),
+                self.stringify(value)
+            )),
+            None => Ok(self.is_truthy(value)),
+        }
+    }
+
+    pub(crate) fn as_number(&self, value: &Arc<dyn Any + Send + Sync>) -> Option<f64> {
+        number::as_float(value)
+    }
+
+    // Reads a number that must be whole, e.g. an index or a range bound
+    pub(crate) fn integer_value(&self, value: &Arc<dyn Any + Send + Sync>, what: &str) -> Result<i64, String> {
+        match self.as_number(value) {
+            Some(n) if n.fract() != 0.0 => Err(format!("{} must be an integer, got {}.", what, n)),
+            Some(n) if n.abs() >= i64::MAX as f64 => Err(format!("{} is too large: {}.", what, self.stringify(value))),
+            Some(n) => Ok(n as i64),
+            None => Err(format!("{} must be a number.", what)),
+        }
+    }
+
+    // Reads a whole number exactly, however large, e.g. an operand of `&`
+    pub(crate) fn exact_integer_value(&self, value: &Arc<dyn Any + Send + Sync>, what: &str) -> Result<BigInt, String> {
+        if let Some(n) = number::as_integer(value) {
+            return Ok(n);
+        }
+        match self.as_number(value) {
+            // A float too large to count in steps of one, or a whole decimal
+            Some(n) if n.is_finite() && n.fract() == 0.0 => Ok(BigInt::from_f64(n).expect("finite")),
+            Some(_) => Err(format!("{} must be an integer, got {}.", what, self.stringify(value))),
+            None => Err(format!("{} must be a number.", what)),
+        }
+    }
+
+    // `& | ^ << >>` work on the exact two's complement value of whole numbers
+    fn bitwise(
+        &self,
+        operator: &Token,
+        left: &Arc<dyn Any + Send + Sync>,
+        right: &Arc<dyn Any + Send + Sync>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        let what = format!("Operand of '{}'", operator.lexeme);
+        let l = self.exact_integer_value(left, &what)?;
+        let r = self.exact_integer_value(right, &what)?;
+
+        let result = match operator.token_type {
+            TokenType::AMPERSAND => l & r,
+            TokenType::PIPE => l | r,
+            TokenType::CARET => l ^ r,
+            _ => {
+                let Some(shift) = r.to_usize().filter(|shift| *shift < 64) else {
+                    return Err(format!("Shift amount must be between 0 and 63, got {}.", r));
+                };
+                if operator.token_type == TokenType::LESS_LESS {
+                    l << shift
+                } else {
+                    l >> shift
+                }
+            }
+        };
+        Ok(number::integer(result))
+    }
+
+    // Turns a possibly negative index into a position, counting negatives from the end
+    fn resolve_index(&self, index_value: &Arc<dyn Any + Send + Sync>, len: usize) -> Result<usize, String> {
+        let index = self.integer_value(index_value, "Array index")?;
+        let position = if index < 0 { index + len as i64 } else { index };
+        if position < 0 || position >= len as i64 {
+            return Err(format!("Array index out of bounds: {} for length {}", index, len));
+        }
+        Ok(position as usize)
+    }
+
+    // Positions selected by `[start:end:step]`, following Python's slice rules
+    fn slice_positions(len: usize, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+        let len = len as i64;
+        let adjust = |i: i64| if i < 0 { i + len } else { i };
+        let mut positions = Vec::new();
+
+        if step > 0 {
+            let mut i = start.map_or(0, adjust).clamp(0, len);
+            let end = end.map_or(len, adjust).clamp(0, len);
+            while i < end {
+                positions.push(i as usize);
+                i += step;
+            }
+        } else {
+            let mut i = start.map_or(len - 1, adjust).clamp(-1, len - 1);
+            let end = end.map_or(-1, adjust).clamp(-1, len - 1);
+            while i > end {
+                positions.push(i as usize);
+                i += step;
+            }
+        }
+        positions
+    }
+
+    fn slice_array(
+        &self,
+        array: &AoiArray,
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        if step == 0 {
+            return Err("Slice step cannot be zero.".to_string());
+        }
+        let elements = array.elements.borrow();
+        let sliced = Self::slice_positions(elements.len(), start, end, step)
+            .into_iter()
+            .map(|i| elements[i].clone())
+            .collect();
+        Ok(Arc::new(AoiArray::new(sliced)))
+    }
+
+    // The copy of `value` kept in a variable; values of unknown type are stored as nil
+    fn stored_value(&self, value: &Arc<dyn Any + Send + Sync>) -> Arc<dyn Any + Send + Sync> {
+        if let Some(v) = value.downcast_ref::<f64>() {
+            Arc::new(*v)
+        } else if let Some(v) = value.downcast_ref::<BigInt>() {
+            Arc::new(v.clone())
+        } else if let Some(v) = value.downcast_ref::<Decimal>() {
+            Arc::new(*v)
+        } else if let Some(v) = value.downcast_ref::<String>() {
+            Arc::new(v.clone())
+        } else if let Some(v) = value.downcast_ref::<bool>() {
+            Arc::new(*v)
+        } else if let Some(v) = value.downcast_ref::<AoiArray>() {
+            Arc::new(v.clone())
+        } else if let Some(v) = value.downcast_ref::<AoiRange>() {
+            Arc::new(v.clone())
+        } else if value.is::<Function>()
+            || value.is::<NativeFunction>()
+            || value.is::<AoiEnum>()
+            || value.is::<EnumConstructor>()
+            || value.is::<EnumValue>()
+            || value.is::<Generator>()
+            || value.is::<BoundMethod>()
+            || value.is::<Task>()
+            || value.is::<Channel>()
+        {
+            value.clone()
+        } else {
+            Arc::new(())
+        }
+    }
+
+    // Collects the names bound by a destructuring pattern, failing when the
+    // value's shape does not fit
+    fn destructure(
+        &self,
+        pattern: &Pattern,
+        value: &Arc<dyn Any + Send + Sync>,
+        bindings: &mut Vec<(Token, Arc<dyn Any + Send + Sync>)>,
+    ) -> Result<(), String> {
+        let (elements, rest) = match pattern {
+            Pattern::Binding(name) => {
+                bindings.push((name.clone(), self.stored_value(value)));
+                return Ok(());
+            }
+            Pattern::Array { elements, rest } => (elements, rest),
+            _ => return Ok(()), // Wildcard; the parser rejects literal patterns here
+        };
+
+        let Some(array) = value.downcast_ref::<AoiArray>() else {
+            return Err(format!("Cannot destructure {}: not an array.", self.stringify(value)));
+        };
+        let items = array.elements.borrow().clone();
+        self.check_unpack_length(elements.len(), rest.is_some(), items.len())?;
+
+        for (element, item) in elements.iter().zip(items.iter()) {
+            self.destructure(element, item, bindings)?;
+        }
+        if let Some(rest) = rest {
+            let remaining = items[elements.len()..].to_vec();
+            bindings.push((rest.clone(), Arc::new(AoiArray::new(remaining))));
+        }
+        Ok(())
+    }
+
+    fn check_unpack_length(&self, expected: usize, has_rest: bool, actual: usize) -> Result<(), String> {
+        if has_rest && actual < expected {
+            Err(format!("Expected at least {} values to unpack but got {}.", expected, actual))
+        } else if !has_rest && actual != expected {
+            Err(format!("Expected {} values to unpack but got {}.", expected, actual))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Stores `value` into a variable or array element
+    fn assign_target(&mut self, target: &Expr, value: Arc<dyn Any + Send + Sync>) -> Result<(), String> {
+        match target {
+            Expr::Variable(variable) => {
+                let stored = self.stored_value(&value);
+                self.environment
+                    .borrow_mut()
+                    .assign(&variable.name, stored)
+                    .map_err(|e| e.to_string())
+            }
+            Expr::Index { object, index } => {
+                let obj_value = self.evaluate(object)?;
+                let index_value = self.evaluate(index)?;
+                self.set_index(&obj_value, &index_value, value)
+            }
+            _ => Err("Invalid assignment target.".to_string()),
+        }
+    }
+
+    fn get_index(
+        &self,
+        obj_value: &Arc<dyn Any + Send + Sync>,
+        index_value: &Arc<dyn Any + Send + Sync>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        // Check if it's an array
+        if let Some(array) = obj_value.downcast_ref::<AoiArray>() {
+            //  Indexing with a range slices the array
+            if let Some(range) = index_value.downcast_ref::<AoiRange>() {
+                return self.slice_array(array, Some(range.start), Some(range.exclusive_end()), 1);
+            }
+
+            let idx = self.resolve_index(index_value, array.len())?;
+            array
+                .get(idx)
+                .ok_or_else(|| format!("Array index out of bounds: {} for length {}", idx, array.len()))
+        } else if let Some(s) = obj_value.downcast_ref::<String>() {
+            //  Strings are indexed by character
+            if let Some(range) = index_value.downcast_ref::<AoiRange>() {
+                return Ok(Arc::new(self.slice_string(s, Some(range.start), Some(range.exclusive_end()), 1)?));
+            }
+
+            let chars: Vec<char> = s.chars().collect();
+            let idx = self.resolve_index(index_value, chars.len())?;
+            Ok(Arc::new(chars[idx].to_string()))
+        } else {
+            Err("Cannot index non-array value.".to_string())
+        }
+    }
+
+    fn set_index(
+        &self,
+        obj_value: &Arc<dyn Any + Send + Sync>,
+        index_value: &Arc<dyn Any + Send + Sync>,
+        new_value: Arc<dyn Any + Send + Sync>,
+    ) -> Result<(), String> {
+        if let Some(array) = obj_value.downcast_ref::<AoiArray>() {
+            let idx = self.resolve_index(index_value, array.len())?;
+
+            if array.set(idx, new_value) {
+                Ok(())
+            } else {
+                Err(format!("Array index out of bounds: {} for length {}", idx, array.len()))
+            }
+        } else if obj_value.is::<String>() {
+            Err("Strings are immutable; build a new string instead.".to_string())
+        } else {
+            Err("Cannot index non-array value.".to_string())
+        }
+    }
+
+    // Equality behind `==` and built-ins such as `index_of`. Every pair
+    // of values can be compared: different types are unequal, arrays and enum
+    // values compare their contents, and functions, tasks and channels their identity.
+    pub(crate) fn values_equal(&self, a: &Arc<dyn Any + Send + Sync>, b: &Arc<dyn Any + Send + Sync>) -> Result<bool, String> {
+        self.values_equal_at(a, b, 0)
+    }
+
+    fn values_equal_at(
+        &self,
+        a: &Arc<dyn Any + Send + Sync>,
+        b: &Arc<dyn Any + Send + Sync>,
+        depth: usize,
+    ) -> Result<bool, String> {
+        if number::is_number(a) && number::is_number(b) {
+            return Ok(number::compare(a, b) == Some(Ordering::Equal));
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<String>(), b.downcast_ref::<String>()) {
+            return Ok(x == y);
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<bool>(), b.downcast_ref::<bool>()) {
+            return Ok(x == y);
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<AoiArray>(), b.downcast_ref::<AoiArray>()) {
+            if Rc::ptr_eq(&x.elements, &y.elements) {
+                return Ok(true);
+            }
+            let (x, y) = (x.elements.borrow(), y.elements.borrow());
+            return self.all_equal(&x, &y, depth);
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<AoiRange>(), b.downcast_ref::<AoiRange>()) {
+            return Ok(x.start == y.start && x.exclusive_end() == y.exclusive_end());
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<EnumValue>(), b.downcast_ref::<EnumValue>()) {
+            if x.enum_name != y.enum_name || x.variant != y.variant {
+                return Ok(false);
+            }
+            return self.all_equal(&x.payload, &y.payload, depth);
+        }
+        if self.is_nil(a) || self.is_nil(b) {
+            return Ok(self.is_nil(a) && self.is_nil(b));
+        }
+        Ok(Arc::ptr_eq(a, b))
+    }
+
+    // Element-wise equality of the contents of two arrays or enum values
+    fn all_equal(
+        &self,
+        x: &[Arc<dyn Any + Send + Sync>],
+        y: &[Arc<dyn Any + Send + Sync>],
+        depth: usize,
+    ) -> Result<bool, String> {
+        if x.len() != y.len() {
+            return Ok(false);
+        }
+        if depth >= MAX_COMPARE_DEPTH {
+            return Err(too_deep_to_compare());
+        }
+        for (p, q) in x.iter().zip(y) {
+            if !self.values_equal_at(p, q, depth + 1)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Ordering behind `<`, `>` and `sort`: numbers by value, strings by
+    // character code, booleans with false first and arrays element by element
+    pub(crate) fn compare_values(
+        &self,
+        a: &Arc<dyn Any + Send + Sync>,
+        b: &Arc<dyn Any + Send + Sync>,
+    ) -> Result<std::cmp::Ordering, String> {
+        self.compare_values_at(a, b, 0)
+    }
+
+    fn compare_values_at(
+        &self,
+        a: &Arc<dyn Any + Send + Sync>,
+        b: &Arc<dyn Any + Send + Sync>,
+        depth: usize,
+    ) -> Result<std::cmp::Ordering, String> {
+        if number::is_number(a) && number::is_number(b) {
+            return number::compare(a, b)
+                .ok_or_else(|| format!("Cannot compare {} and {}.", self.stringify(a), self.stringify(b)));
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<String>(), b.downcast_ref::<String>()) {
+            return Ok(x.cmp(y));
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<bool>(), b.downcast_ref::<bool>()) {
+            return Ok(x.cmp(y));
+        }
+        if let (Some(x), Some(y)) = (a.downcast_ref::<AoiArray>(), b.downcast_ref::<AoiArray>()) {
+            let (x, y) = (x.elements.borrow().clone(), y.elements.borrow().clone());
+            if depth >= MAX_COMPARE_DEPTH && !x.is_empty() && !y.is_empty() {
+                return Err(too_deep_to_compare());
+            }
+            for (p, q) in x.iter().zip(y.iter()) {
+                let ordering = self.compare_values_at(p, q, depth + 1)?;
+                if ordering != Ordering::Equal {
+                    return Ok(ordering);
+                }
+            }
+            return Ok(x.len().cmp(&y.len())); // A prefix comes first
+        }
+        if self.type_name(a) == self.type_name(b) {
+            return Err(format!("Values of type {} have no order.", self.type_name(a)));
+        }
+        Err(format!(
+            "Cannot compare {} with {}.",
+            self.type_name(a),
+            self.type_name(b)
+        ))
+    }
+
+    // Name of a value's type for error messages
+    pub(crate) fn type_name(&self, value: &Arc<dyn Any + Send + Sync>) -> &'static str {
+        if number::is_number(value) {
+            "number"
+        } else if value.is::<String>() {
+            "string"
+        } else if value.is::<bool>() {
+            "bool"
+        } else if value.is::<AoiArray>() {
+            "array"
+        } else if value.is::<AoiRange>() {
+            "range"
+        } else if value.is::<Function>() || value.is::<NativeFunction>() || value.is::<BoundMethod>() {
+            "function"
+        } else if value.is::<AoiEnum>() || value.is::<EnumConstructor>() || value.is::<EnumValue>() {
+            "enum"
+        } else if value.is::<Generator>() {
+            "generator"
+        } else if value.is::<Task>() {
+            "task"
+        } else if value.is::<Channel>() {
+            "channel"
+        } else {
+            "nil"
+        }
+    }
+
+    // Entry point of the iteration protocol
+    fn iterate(&self, value: &Arc<dyn Any + Send + Sync>) -> Result<Box<dyn AoiIterator>, String> {
+        if let Some(array) = value.downcast_ref::<AoiArray>() {
+            Ok(Box::new(ArrayIterator {
+                array: array.clone(),
+                position: 0,
+            }))
+        } else if let Some(s) = value.downcast_ref::<String>() {
+            Ok(Box::new(StringIterator {
+                chars: s.chars().collect(),
+                position: 0,
+            }))
+        } else if let Some(range) = value.downcast_ref::<AoiRange>() {
+            Ok(Box::new(RangeIterator {
+                current: range.start,
+                end: range.exclusive_end(),
+            }))
+        } else if value.is::<Generator>() {
+            Ok(Box::new(GeneratorIterator {
+                generator: value.clone(),
+            }))
+        } else if value.is::<Channel>() {
+            Ok(Box::new(ChannelIterator {
+                channel: value.clone(),
+            }))
+        } else {
+            Err(format!("Cannot iterate over {}.", self.stringify(value)))
+        }
+    }
+
+    // Strings are sliced by character, like arrays are sliced by element
+    pub(crate) fn slice_string(
+        &self,
+        s: &str,
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+    ) -> Result<String, String> {
+        if step == 0 {
+            return Err("Slice step cannot be zero.".to_string());
+        }
+        let chars: Vec<char> = s.chars().collect();
+        Ok(Self::slice_positions(chars.len(), start, end, step)
+            .into_iter()
+            .map(|i| chars[i])
+            .collect())
+    }
+
+    fn is_nil(&self, value: &Arc<dyn Any + Send + Sync>) -> bool {
+        value.is::<()>() || matches!(value.downcast_ref::<TokenLiteral>(), Some(TokenLiteral::Null))
+    }
+
+    // Checks `value` against `pattern`, collecting the variables it binds
+    fn match_pattern(
+        &self,
+        pattern: &Pattern,
+        value: &Arc<dyn Any + Send + Sync>,
+        bindings: &mut Vec<(String, Arc<dyn Any + Send + Sync>)>,
+    ) -> bool {
+        match pattern {
+            Pattern::Wildcard => true,
+            Pattern::Binding(name) => {
+                bindings.push((name.lexeme.clone(), value.clone()));
+                true
+            }
+            Pattern::Literal(literal) => match literal {
+                TokenLiteral::Number(n) => self.as_number(value) == Some(*n),
+                TokenLiteral::Decimal(d) => number::as_decimal(value) == Some(*d),
+                TokenLiteral::String(s) => value.downcast_ref::<String>() == Some(s),
+                TokenLiteral::Boolean(b) => value.downcast_ref::<bool>() == Some(b),
+                TokenLiteral::Null => self.is_nil(value),
+                TokenLiteral::Identifier(_) => false,
+            },
+            Pattern::Range { start, end, inclusive } => match self.as_number(value) {
+                Some(n) if *inclusive => *start <= n && n <= *end,
+                Some(n) => *start <= n && n < *end,
+                None => false,
+            },
+            Pattern::Array { elements, rest } => {
+                let Some(array) = value.downcast_ref::<AoiArray>() else {
+                    return false;
+                };
+                let items = array.elements.borrow().clone();
+                let length_ok = if rest.is_some() {
+                    items.len() >= elements.len()
+                } else {
+                    items.len() == elements.len()
+                };
+                if !length_ok {
+                    return false;
+                }
+                for (element, item) in elements.iter().zip(items.iter()) {
+                    if !self.match_pattern(element, item, bindings) {
+                        return false;
+                    }
+                }
+                if let Some(rest) = rest {
+                    let remaining = items[elements.len()..].to_vec();
+                    bindings.push((rest.lexeme.clone(), Arc::new(AoiArray::new(remaining))));
+                }
+                true
+            }
+            Pattern::Variant { enum_name, variant, fields } => {
+                let Some(value) = value.downcast_ref::<EnumValue>() else {
+                    return false;
+                };
+                value.enum_name == enum_name.lexeme
+                    && value.variant == variant.lexeme
+                    && value.payload.len() == fields.len()
+                    && fields
+                        .iter()
+                        .zip(&value.payload)
+                        .all(|(field, item)| self.match_pattern(field, item, bindings))
+            }
+        }
+    }
+
+    // Finds the first arm whose pattern and guard accept `value`, returning it
+    // together with the environment holding its bindings
+    fn select_arm<'a, T>(
+        &mut self,
+        value: &Arc<dyn Any + Send + Sync>,
+        arms: &'a [MatchArm<T>],
+    ) -> Result<(&'a MatchArm<T>, Rc<RefCell<Environment>>), String> {
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !self.match_pattern(&arm.pattern, value, &mut bindings) {
+                continue;
+            }
+
+            let environment = Rc::new(RefCell::new(Environment::new(Some(self.environment.clone()))));
+            for (name, bound) in bindings {
+                environment.borrow_mut().define(name, bound);
+            }
+
+            if let Some(guard) = &arm.guard {
+                let previous = self.environment.clone();
+                self.environment = environment.clone();
+                let result = self.evaluate(guard);
+                self.environment = previous;
+                if !self.condition(&result?)? {
+                    continue;
+                }
+            }
+
+            return Ok((arm, environment));
+        }
+
+        Err(format!("No match arm matched value {}.", self.stringify(value)))
+    }
+
+    pub fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), String> {
+        let previous = self.environment.clone(); //  Save old environment
+        self.environment = environment.clone();
+
+        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+
+        self.environment = previous; // Restore previous environment after execution
+        result
+    }
+
+    
+    // Calls any callable value; used by call expressions and by built-ins that take callbacks
+    pub(crate) fn call_value(
+        &mut self,
+        callee: &Arc<dyn Any + Send + Sync>,
+        arguments: Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        if let Some(native) = callee.downcast_ref::<NativeFunction>() {
+            return native.call(self, &arguments);
+        }
+        if let Some(bound) = callee.downcast_ref::<BoundMethod>() {
+            let mut with_receiver = vec![bound.receiver.clone()];
+            with_receiver.extend(arguments);
+            return bound.method.call(self, &with_receiver);
+        }
+        if let Some(constructor) = callee.downcast_ref::<EnumConstructor>() {
+            if arguments.len() != constructor.fields.len() {
+                return Err(format!(
+                    "{}.{}() expected {} arguments but got {}.",
+                    constructor.enum_name,
+                    constructor.variant,
+                    constructor.fields.len(),
+                    arguments.len()
+                ));
+            }
+            return Ok(Arc::new(EnumValue {
+                enum_name: constructor.enum_name.clone(),
+                variant: constructor.variant.clone(),
+                fields: constructor.fields.clone(),
+                payload: arguments.iter().map(|a| self.stored_value(a)).collect(),
+            }));
+        }
+
+        if let Some(value) = callee.downcast_ref::<EnumValue>() {
+            // `E.A` without fields is already a value, not a constructor
+            return Err(format!("Variant '{}.{}' takes no payload.", value.enum_name, value.variant));
         }
+
+        let function = callee
+            .downcast_ref::<Function>()
+            .ok_or_else(|| format!("Expected function, found {}.", self.type_name(callee)))?;
+        self.call_function(function, arguments)
     }
 
+    // Calls a callback and reads its result as a condition
+    pub(crate) fn call_predicate(
+        &mut self,
+        callee: &Arc<dyn Any + Send + Sync>,
+        item: Arc<dyn Any + Send + Sync>,
+        name: &str,
+    ) -> Result<bool, String> {
+        let result = self
+            .call_value(callee, vec![item])
+            .map_err(|e| format!("{} (in {}() callback)", e, name))?;
+        self.condition(&result).map_err(|e| format!("{} (in {}() callback)", e, name))
+    }
+
+    fn call_function(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        if function.generator {
+            return self.start_generator(function, arguments);
+        }
+        self.run_function(function, arguments)
+    }
+
+    // Binds the arguments; the body first runs on the first `next()`
+    fn start_generator(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        let environment = Rc::new(RefCell::new(Environment::new(Some(function.closure.clone()))));
+        for (param, arg) in function.params.iter().zip(arguments) {
+            environment.borrow_mut().define(param.lexeme.clone(), arg);
+        }
+
+        let state = GeneratorState {
+            steps: function.steps.clone().expect("generators have steps"),
+            next: 0,
+            environment,
+            scopes: Vec::new(),
+            iterators: Vec::new(),
+        };
+        Ok(Arc::new(Generator {
+            name: function.name.clone(),
+            state: RefCell::new(Some(state)),
+        }))
+    }
+
+    // Runs the generator until its next `yield`. `None` once the body has finished.
+    pub(crate) fn resume_generator(&mut self, generator: &Generator) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let Ok(mut state) = generator.state.try_borrow_mut() else {
+            return Err(format!("Generator '{}' is already running.", generator.name));
+        };
+        let Some(running) = state.as_mut() else {
+            return Ok(None);
+        };
+
+        self.enter_call(&generator.name)?;
+        let previous = std::mem::replace(&mut self.environment, running.environment.clone());
+        let result = self.run_steps(running);
+        running.environment = std::mem::replace(&mut self.environment, previous);
+        self.call_stack.pop();
+
+        if !matches!(result, Ok(Some(_))) {
+            *state = None; // Finished or failed; its scopes can go
+        }
+        result
+    }
+
+    // Runs a generator's steps from where it stopped. Returns the yielded value,
+    // or `None` when the body finishes.
+    fn run_steps(&mut self, state: &mut GeneratorState) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let steps = state.steps.clone();
+        while let Some(step) = steps.get(state.next) {
+            state.next += 1;
+            match step {
+                generator::Step::Run(stmt) => match self.execute(stmt) {
+                    Err(e) if e == RETURN_SIGNAL => {
+                        // A generator's return value is dropped, but a tail call still has to run
+                        self.return_value = None;
+                        if let Some(TailCall { callee, arguments }) = self.tail_call.take() {
+                            self.call_value(&callee, arguments)?;
+                        }
+                        return Ok(None);
+                    }
+                    result => result?,
+                },
+                generator::Step::Yield(value) => {
+                    let value = match value {
+                        Some(expr) => self.evaluate(expr)?,
+                        None => Arc::new(()),
+                    };
+                    return Ok(Some(value));
+                }
+                generator::Step::Enter => {
+                    let environment = Environment::new(Some(self.environment.clone()));
+                    let environment = Rc::new(RefCell::new(environment));
+                    state.scopes.push(std::mem::replace(&mut self.environment, environment));
+                }
+                generator::Step::Leave => {
+                    self.environment = state.scopes.pop().expect("every scope is entered first");
+                }
+                generator::Step::Jump(target) => state.next = *target,
+                generator::Step::JumpUnless(condition, target) => {
+                    let result = self.evaluate(condition)?;
+                    if !self.condition(&result)? {
+                        state.next = *target;
+                    }
+                }
+                generator::Step::Iterate(iterable) => {
+                    let collection = self.evaluate(iterable)?;
+                    state.iterators.push((self.iterate(&collection)?, 0));
+                }
+                generator::Step::Next { index, variable, done } => {
+                    let (iterator, count) = state.iterators.last_mut().expect("`for-in` starts iterating first");
+                    let Some(item) = iterator.next(self)? else {
+                        state.iterators.pop();
+                        state.next = *done;
+                        continue;
+                    };
+                    // Each iteration gets its own scope, as in `Stmt::ForIn`
+                    let environment = Rc::new(RefCell::new(Environment::new(Some(self.environment.clone()))));
+                    if let Some(index) = index {
+                        environment
+                            .borrow_mut()
+                            .define(index.lexeme.clone(), Arc::new(*count as f64));
+                    }
+                    environment.borrow_mut().define(variable.lexeme.clone(), item);
+                    *count += 1;
+                    state.scopes.push(std::mem::replace(&mut self.environment, environment));
+                }
+                generator::Step::Match { subject, arms } => {
+                    let value = self.evaluate(subject)?;
+                    let (arm, environment) = self.select_arm(&value, arms)?;
+                    state.next = arm.body;
+                    state.scopes.push(std::mem::replace(&mut self.environment, environment));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn spawn_task(
+        &mut self,
+        callee: Arc<dyn Any + Send + Sync>,
+        arguments: Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        let name = if let Some(function) = callee.downcast_ref::<Function>() {
+            function.name.clone()
+        } else if let Some(native) = callee.downcast_ref::<NativeFunction>() {
+            native.name.to_string()
+        } else if callee.is::<BoundMethod>() || callee.is::<EnumConstructor>() {
+            self.stringify(&callee)
+        } else {
+            return Err("spawn() expects a function.".to_string());
+        };
+        if self.scheduler.unfinished().len() >= MAX_TASKS {
+            return Err(format!("Too many tasks: at most {} can be unfinished at once.", MAX_TASKS));
+        }
+
+        let seed = self.for_coroutine(self.environment.clone());
+        let coroutine = Coroutine::spawn(move |link| {
+            let mut interpreter = seed.grow(link);
+            let result = interpreter.call_value(&callee, arguments);
+            (result, interpreter.output)
+        });
 
+        let coroutine = Arc::new(Mutex::new(coroutine));
+        self.coroutines.register(&coroutine);
+        let task = Arc::new(Task::new(name, coroutine));
+        self.scheduler.add(task.clone());
+        self.scheduler.notify(); // A new task is something to run
+        Ok(task)
+    }
 
-    pub fn interpret(&mut self, statements: &[Stmt]) -> String {
-        for statement in statements {
-            if let Err(err) = self.visit_stmt(statement) {
-                self.output.push_str(&format!("Runtime error: {}\n", err));
+    // Gives every unfinished task a turn. Returns whether any of them got
+    // something done that a waiter could be waiting for.
+    fn run_tasks(&mut self) -> bool {
+        let before = self.scheduler.progress();
+        for task in self.scheduler.round() {
+            // Locked when an enclosing task is the one waiting
+            let Ok(mut coroutine) = task.coroutine.try_lock() else {
+                continue;
+            };
+            if coroutine.is_finished() {
+                continue;
+            }
+            let (step, output) = coroutine.resume();
+            drop(coroutine);
+
+            self.output.push_str(&output);
+            match step {
+                Ok(Step::Suspended) => {} // Waiting for something
+                Ok(Step::Finished(value)) => self.scheduler.finish(&task, Ok(value)),
+                Err(err) => {
+                    if let Some(code) = exit_request(&err) {
+                        self.exit_code = Some(code);
+                        return false; // Nothing else runs
+                    }
+                    // Reported by whoever joins the task, or else when the program ends
+                    self.scheduler.finish(&task, Err(err));
+                }
             }
         }
-        self.output.clone()
-    }
-    
-    
-    
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), String> {
-        self.visit_stmt(stmt)
+        self.scheduler.progress() != before
     }
-    fn is_truthy(&self, value: &Arc<dyn Any + Send + Sync>) -> bool {
-        if let Some(b) = value.downcast_ref::<bool>() {
-            *b
-        } else if let Some(n) = value.downcast_ref::<f64>() {
-            *n != 0.0
-        } else if let Some(s) = value.downcast_ref::<String>() {
-            !s.is_empty()
-        } else {
-            false
+
+    // Reports what the tasks left behind once the program is over: failures
+    // that no `join` reported, and tasks still waiting for something. Those are
+    // left out when the program called `exit` or already stopped on an error.
+    fn report_tasks(&mut self) {
+        let stopped = self.exit_code.is_some() || self.failed;
+        for task in self.scheduler.unjoined_failures() {
+            if let Some(Err(err)) = task.result() {
+                self.output
+                    .push_str(&format!("Runtime error in task '{}': {}\n", task.name, err));
+                self.failed = true;
+            }
+        }
+        if stopped {
+            return;
+        }
+        for task in self.scheduler.unfinished() {
+            self.output.push_str(&format!(
+                "Runtime error in task '{}': Deadlock: still blocked when the program ended.\n",
+                task.name
+            ));
+            self.failed = true;
         }
     }
 
-    pub fn execute_block(
+    // Retries `attempt` until it succeeds. A task gives control back to the
+    // scheduler in between; the main program runs the tasks itself.
+    fn wait_for<T>(
         &mut self,
-        statements: &[Stmt],
-        environment: Rc<RefCell<Environment>>,
-    ) -> Result<(), String> {
-        let previous = self.environment.clone(); //  Save old environment
-        self.environment = environment.clone();
+        waiting: &str,
+        mut attempt: impl FnMut() -> Result<Attempt<T>, String>,
+    ) -> Result<T, String> {
+        loop {
+            if let Attempt::Done(value) = attempt()? {
+                return Ok(value);
+            }
+            if self.in_task {
+                let link = self.coroutine.as_ref().expect("tasks run as coroutines");
+                let output = std::mem::take(&mut self.output);
+                link.suspend(output)?;
+            } else if !self.run_tasks() {
+                if let Some(code) = self.exit_code {
+                    return Err(format!("{}{}", EXIT_SIGNAL, code)); // A task called `exit`
+                }
+                return Err(format!("Deadlock: every task is blocked while {}.", waiting));
+            }
+        }
+    }
 
-        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+    pub(crate) fn join_task(&mut self, task: &Task) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        let waiting = format!("waiting for task '{}'", task.name);
+        let result = self.wait_for(&waiting, || match task.result() {
+            Some(result) => Ok(Attempt::Done(result)),
+            None => Ok(Attempt::WouldBlock),
+        })?;
+        task.set_joined();
+        result.map_err(|err| format!("Task '{}' failed: {}", task.name, err))
+    }
 
-        self.environment = previous; // Restore previous environment after execution
+    pub(crate) fn send(&mut self, channel: &Channel, value: Arc<dyn Any + Send + Sync>) -> Result<(), String> {
+        self.wait_for("sending to a full channel", || channel.try_send(value.clone()))?;
+        self.scheduler.notify();
+        Ok(())
+    }
+
+    // `None` once the channel is closed and empty
+    pub(crate) fn receive(&mut self, channel: &Channel) -> Result<Option<Arc<dyn Any + Send + Sync>>, String> {
+        let value = self.wait_for("receiving from a channel", || Ok(channel.try_recv()))?;
+        self.scheduler.notify();
+        Ok(value)
+    }
+
+    pub(crate) fn close_channel(&mut self, channel: &Channel) {
+        channel.close();
+        self.scheduler.notify();
+    }
+
+    fn run_function(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Result<Arc<dyn Any + Send + Sync>, String> {
+        self.enter_call(&function.name)?;
+        let result = self.run_body(function, arguments);
+        self.call_stack.pop();
         result
     }
 
-    
-    fn call_function(
+    // Runs a function body, then the body of each function it tail-calls in
+    // the same Rust frame, so `return f(...)` does not grow the stack
+    fn run_body(
         &mut self,
         function: &Function,
         arguments: Vec<Arc<dyn Any + Send + Sync>>,
     ) -> Result<Arc<dyn Any + Send + Sync>, String> {
-        let environment = Rc::new(RefCell::new(Environment::new(Some(function.closure.clone()))));
-    
-        // Bind function parameters to arguments
-        for (param, arg) in function.params.iter().zip(arguments.iter()) {
-            environment.borrow_mut().define(param.lexeme.clone(), arg.clone());
-        }
-    
-        // Store previous environment and switch to function's environment
-        let previous_environment = self.environment.clone();
-        self.environment = environment.clone();
-    
-        let mut return_value: Option<Arc<dyn Any + Send + Sync>> = None;
-    
-        for stmt in &function.body {
-            match self.execute(&**stmt) {
-                Err(e) if e.starts_with("Return:") => {
-                    // Extract the value from the error message
-                    let value_str = e.strip_prefix("Return: ").unwrap_or("");
-    
-                    // Try to convert common types
-                    if value_str == "true" {
-                        return_value = Some(Arc::new(true));
-                    } else if value_str == "false" {
-                        return_value = Some(Arc::new(false));
-                    } else if let Ok(num) = value_str.parse::<i64>() {
-                        return_value = Some(Arc::new(num));
-                    } else if let Ok(num) = value_str.parse::<f64>() {
-                        return_value = Some(Arc::new(num));
-                    } else {
-                        // Prevent issues with large numbers or incorrect values
-                        // Checking for extremely large values and handling them appropriately
-                        if value_str.len() > 100 {
-                            return Err("Value too large to process.".to_string());
-                        }
-    
-                        // Remove the debug formatting artifacts from the string
-                        let cleaned_str = value_str.trim()
-                            .trim_start_matches("Some(")
-                            .trim_end_matches(")")
-                            .trim_matches('"');
-                        return_value = Some(Arc::new(cleaned_str.to_string()));
+        let mut tail_callee: Option<Arc<dyn Any + Send + Sync>> = None;
+        let mut arguments = arguments;
+
+        loop {
+            let function = match &tail_callee {
+                Some(callee) => callee.downcast_ref::<Function>().expect("tail calls only target functions"),
+                None => function,
+            };
+            let environment = Rc::new(RefCell::new(Environment::new(Some(function.closure.clone()))));
+
+            // Bind function parameters to arguments
+            for (param, arg) in function.params.iter().zip(arguments.iter()) {
+                environment.borrow_mut().define(param.lexeme.clone(), arg.clone());
+            }
+
+            // Store previous environment and switch to function's environment
+            let previous_environment = self.environment.clone();
+            self.environment = environment.clone();
+
+            let mut return_value: Option<Arc<dyn Any + Send + Sync>> = None;
+
+            for stmt in &function.body {
+                match self.execute(stmt) {
+                    Err(e) if e == RETURN_SIGNAL => {
+                        // `Stmt::Return` left the value behind before unwinding
+                        return_value = self.return_value.take();
+                        break;
                     }
-                    break;
-                }
-                Err(e) => {
-                    self.environment = previous_environment;
-                    return Err(e);
+                    Err(e) => {
+                        self.environment = previous_environment;
+                        return Err(e);
+                    }
+                    Ok(_) => continue,
                 }
-                Ok(_) => continue,
             }
+
+            // Restore previous environment
+            self.environment = previous_environment;
+
+            let Some(TailCall { callee, arguments: next_arguments }) = self.tail_call.take() else {
+                return Ok(return_value.unwrap_or_else(|| Arc::new(0_i64)));
+            };
+            if let (Some(name), Some(next)) = (self.call_stack.last_mut(), callee.downcast_ref::<Function>()) {
+                name.clone_from(&next.name); // The tail call replaces this frame
+            }
+            tail_callee = Some(callee);
+            arguments = next_arguments;
         }
-    
-        // Restore previous environment
-        self.environment = previous_environment;
-    
-        Ok(return_value.unwrap_or_else(|| Arc::new(0_i64)))
     }
-    
+
+    // Records a call, failing with a "stack overflow" error when calls nest too deeply
+    fn enter_call(&mut self, name: &str) -> Result<(), String> {
+        let stack_used = self.stack_start.abs_diff(stack_address());
+        if self.call_stack.len() >= MAX_CALL_DEPTH || stack_used > STACK_SIZE - STACK_RESERVE {
+            return Err(format!(
+                "Stack overflow after {} nested calls: {}",
+                self.call_stack.len(),
+                self.call_chain()
+            ));
+        }
+        self.call_stack.push(name.to_string());
+        Ok(())
+    }
+
+    // The functions being run, outermost first, with repeats collapsed: `main → fib ×500`
+    fn call_chain(&self) -> String {
+        let mut groups: Vec<(&str, usize)> = Vec::new();
+        for name in &self.call_stack {
+            match groups.last_mut() {
+                Some((last, count)) if *last == name.as_str() => *count += 1,
+                _ => groups.push((name, 1)),
+            }
+        }
+        let mut parts: Vec<String> = groups
+            .iter()
+            .map(|(name, count)| if *count > 1 { format!("{} ×{}", name, count) } else { name.to_string() })
+            .collect();
+        if parts.len() > 10 {
+            parts.splice(5..parts.len() - 5, ["…".to_string()]);
+        }
+        parts.join(" → ")
+    }
 
     fn visit_stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
         match stmt {
             Stmt::Return { value, .. } => {
+                // `return f(...)` on a function leaves the call to `run_body` after unwinding
+                if let Some(Expr::Call { callee, arguments }) = value {
+                    let callee = self.evaluate(callee)?;
+                    let mut args = Vec::new();
+                    for arg in arguments {
+                        args.push(self.evaluate(arg)?);
+                    }
+                    if callee.downcast_ref::<Function>().is_some_and(|f| !f.generator) && !self.call_stack.is_empty() {
+                        self.tail_call = Some(TailCall { callee, arguments: args });
+                        return Err(RETURN_SIGNAL.to_string());
+                    }
+                    self.return_value = Some(self.call_value(&callee, args)?);
+                    return Err(RETURN_SIGNAL.to_string());
+                }
+
                 let value = if let Some(expr) = value {
                     self.evaluate(expr)?
                 } else {
                     Arc::new(())
                 };
-                
-                // Create a cleaner string representation for the return value
-                let return_str = if let Some(v) = value.downcast_ref::<i64>() {
-                    format!("Return: {}", v)
-                } else if let Some(v) = value.downcast_ref::<f64>() {
-                    format!("Return: {}", v)
-                } else if let Some(v) = value.downcast_ref::<bool>() {
-                    format!("Return: {}", v)
-                } else if let Some(v) = value.downcast_ref::<String>() {
-                    format!("Return: {}", v)
-                } else if value.is::<()>() {
-                    String::from("Return: nil")
-                } else {
-                    format!("Return: {}", self.stringify(&value))
-                };
-                
-                Err(return_str)
+
+                // Unwind to `call_function` through the error channel, carrying the value on the side
+                self.return_value = Some(value);
+                Err(RETURN_SIGNAL.to_string())
+            }
+            Stmt::ForIn {
+                index,
+                variable,
+                iterable,
+                body,
+            } => {
+                let collection = self.evaluate(iterable)?;
+                let mut iterator = self.iterate(&collection)?;
+
+                let mut count = 0;
+                while let Some(item) = iterator.next(self)? {
+                    // Each iteration gets its own scope so closures capture that iteration's value
+                    let environment = Rc::new(RefCell::new(Environment::new(Some(self.environment.clone()))));
+                    if let Some(index) = index {
+                        environment
+                            .borrow_mut()
+                            .define(index.lexeme.clone(), Arc::new(count as f64));
+                    }
+                    environment.borrow_mut().define(variable.lexeme.clone(), item);
+                    self.execute_block(std::slice::from_ref(body), environment)?;
+                    count += 1;
+                }
+                Ok(())
             }
-            Stmt::Function { name, params, body } => {
+            Stmt::Match { subject, arms } => {
+                let value = self.evaluate(subject)?;
+                let (arm, environment) = self.select_arm(&value, arms)?;
+                self.execute_block(std::slice::from_ref(&arm.body), environment)
+            }
+            Stmt::Function { name, params, param_types, return_type, body, constant, generator, doc } => {
                 let function = Arc::new(Function::new(
                     name.lexeme.clone(),
                     params.clone(),
-                    body.iter().map(|stmt| Box::new(stmt.clone())).collect(),
+                    body.clone(),
                     self.environment.clone(),
+                    *generator,
+                    docgen::signature(name, params, param_types, return_type),
+                    doc.clone(),
                 ));
-            
-                self.environment.borrow_mut().define(
-                    name.lexeme.clone(),
-                    function.clone() as Arc<dyn Any + Send + Sync>, // Ensure it's stored as a dynamic type
-                );
-            
+
+                let mut environment = self.environment.borrow_mut();
+                environment.check_redeclare(name).map_err(|e| e.to_string())?;
+                if *constant {
+                    environment.define_constant(name, function as Arc<dyn Any + Send + Sync>);
+                } else {
+                    environment.define(
+                        name.lexeme.clone(),
+                        function.clone() as Arc<dyn Any + Send + Sync>, // Ensure it's stored as a dynamic type
+                    );
+                }
+
+                Ok(())
+            }
+            Stmt::Const { name, initializer, .. } => {
+                let value = self.evaluate(initializer)?;
+                let mut environment = self.environment.borrow_mut();
+                environment.check_redeclare(name).map_err(|e| e.to_string())?;
+                environment.define_constant(name, value);
                 Ok(())
             }
             
@@ -244,7 +1546,7 @@ impl Interpreter {
                 while {
                     if let Some(cond) = condition {
                         let result = self.evaluate(cond)?;
-                        self.is_truthy(&result)
+                        self.condition(&result)?
                     } else {
                         true
                     }
@@ -277,10 +1579,10 @@ impl Interpreter {
                     .map_err(|e| e.to_string())
             }
 
-            Stmt::While { condition, body } => {
+            Stmt::While { condition, body, .. } => {
                 while {
                     let result = self.evaluate(condition)?;
-                    self.is_truthy(&result)
+                    self.condition(&result)?
                 } {
                     self.execute(body)?;
                 }
@@ -292,54 +1594,105 @@ impl Interpreter {
                 self.execute_block(statements, Rc::new(RefCell::new(new_env)))
             }
 
+            // Runs only under the test runner; a failure is recorded, not raised
+            Stmt::Test { name, body } => {
+                let Some(results) = self.test_results.as_mut() else {
+                    return Ok(());
+                };
+                results.push(TestResult {
+                    name: name.literal.as_ref().map(|name| name.to_string()).unwrap_or_default(),
+                    error: None,
+                });
+
+                let new_env = Environment::new(Some(self.environment.clone()));
+                match self.execute_block(body, Rc::new(RefCell::new(new_env))) {
+                    Err(err) if exit_request(&err).is_none() => {
+                        if let Some(result) = self.test_results.as_mut().and_then(|results| results.last_mut()) {
+                            result.error = Some(err);
+                        }
+                        Ok(())
+                    }
+                    result => result,
+                }
+            }
+
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
+                ..
             } => {
                 let condition = self.evaluate(condition)?;
-                if let Some(b) = condition.downcast_ref::<bool>() {
-                    if *b {
-                        self.execute(then_branch)
-                    } else if let Some(else_branch) = else_branch {
-                        self.execute(else_branch)
-                    } else {
-                        Ok(())
-                    }
+                if self.condition(&condition)? {
+                    self.execute(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)
                 } else {
-                    Err("Condition must be a boolean.".to_string())
+                    Ok(())
                 }
             }
 
-            Stmt::Var { name, initializer } => {
+            // Generators run their `yield`s as steps; one reached here has no generator
+            Stmt::Yield { .. } => Err("Can only yield inside a generator.".to_string()),
+            Stmt::Enum { name, variants } => {
+                let enumeration = AoiEnum {
+                    name: name.lexeme.clone(),
+                    variants: variants.clone(),
+                };
+                let mut environment = self.environment.borrow_mut();
+                environment.check_redeclare(name).map_err(|e| e.to_string())?;
+                environment.define(name.lexeme.clone(), Arc::new(enumeration));
+                Ok(())
+            }
+            Stmt::Destructure { pattern, initializer } => {
+                let value = self.evaluate(initializer)?;
+                let mut bindings = Vec::new();
+                self.destructure(pattern, &value, &mut bindings)?;
+
+                let mut environment = self.environment.borrow_mut();
+                for (name, bound) in bindings {
+                    environment.check_redeclare(&name).map_err(|e| e.to_string())?;
+                    environment.define(name.lexeme, bound);
+                }
+                Ok(())
+            }
+            Stmt::Var { name, initializer, .. } => {
                 let value = if let Some(init) = initializer {
                     self.evaluate(init)?
                 } else {
                     Arc::new(())
                 };
-                let cloned_value = if let Some(v) = value.downcast_ref::<f64>() {
-                    Arc::new(*v) as Arc<dyn Any + Send + Sync>
-                } else if let Some(v) = value.downcast_ref::<String>() {
-                    Arc::new(v.clone()) as Arc<dyn Any + Send + Sync>
-                } else if let Some(v) = value.downcast_ref::<bool>() {
-                    Arc::new(*v) as Arc<dyn Any + Send + Sync>
-                } else if let Some(v) = value.downcast_ref::<AoiArray>() {
-                    Arc::new(v.clone()) as Arc<dyn Any + Send + Sync>
-                } else {
-                    Arc::new(()) as Arc<dyn Any + Send + Sync>
-                };
-                self.environment
-                    .borrow_mut()
-                    .define(name.clone(), cloned_value);
+                let cloned_value = self.stored_value(&value);
+                let mut environment = self.environment.borrow_mut();
+                environment.check_redeclare(name).map_err(|e| e.to_string())?;
+                environment.define(name.lexeme.clone(), cloned_value);
                 Ok(())
             }
             Stmt::Expression { expression } => {
                 self.evaluate(expression)?;
                 Ok(())
             }
-            Stmt::Print { expression } => {
-                let value = self.evaluate(expression)?;
-                let output_line = format!("{}\n", self.stringify(&value));
+            Stmt::Print { expressions, separator, newline, stderr } => {
+                let separator = match separator {
+                    Some(separator) => {
+                        let value = self.evaluate(separator)?;
+                        self.stringify(&value)
+                    }
+                    None => " ".to_string(),
+                };
+                let mut parts = Vec::new();
+                for expression in expressions {
+                    let value = self.evaluate(expression)?;
+                    parts.push(format::format_value(self, &value, &format::Spec::default())?);
+                }
+                let mut output_line = parts.join(&separator);
+                if *newline {
+                    output_line.push('\n');
+                }
+                if *stderr && !self.sandboxed {
+                    eprint!("{}", output_line); // Straight out, unlike the captured output
+                    return Ok(());
+                }
                 self.output.push_str(&output_line); // <-- Capture output
                 Ok(())
             }
@@ -347,7 +1700,7 @@ impl Interpreter {
         }
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> Result<Arc<dyn Any + Send + Sync>, String> {
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<Arc<dyn Any + Send + Sync>, String> {
         match expr {
             Expr::Array { elements } => {
                 let mut array: Vec<Arc<dyn Any + Send + Sync>> = Vec::new();
@@ -360,72 +1713,151 @@ impl Interpreter {
             Expr::Index { object, index } => {
                 let obj_value = self.evaluate(object)?;
                 let index_value = self.evaluate(index)?;
+                self.get_index(&obj_value, &index_value)
+            }
 
-                // Check if it's an array
-                if let Some(array) = obj_value.downcast_ref::<AoiArray>() {
-                    let idx = if let Some(&i) = index_value.downcast_ref::<f64>() {
-                        i as usize
-                    } else if let Some(&i) = index_value.downcast_ref::<i64>() {
-                        i as usize
-                    } else {
-                        return Err("Array index must be a number.".to_string());
-                    };
+            Expr::SafeIndex { object, index } => {
+                let obj_value = self.evaluate(object)?;
+                if self.is_nil(&obj_value) {
+                    return Ok(Arc::new(()));
+                }
+                let index_value = self.evaluate(index)?;
 
-                    if let Some(value) = array.get(idx) {
-                        Ok(value)
-                    } else {
-                        Err(format!("Array index out of bounds: {} >= {}", idx, array.len()))
+                let len = match obj_value.downcast_ref::<AoiArray>() {
+                    Some(array) => Some(array.len()),
+                    None => obj_value.downcast_ref::<String>().map(|s| s.chars().count()),
+                };
+                if let (Some(len), false) = (len, index_value.is::<AoiRange>()) {
+                    let position = self.integer_value(&index_value, "Array index")?;
+                    if position >= len as i64 || position < -(len as i64) {
+                        return Ok(Arc::new(()));
                     }
-                } else {
-                    Err("Cannot index non-array value.".to_string())
                 }
+                self.get_index(&obj_value, &index_value)
             }
 
             Expr::IndexAssign { object, index, value } => {
                 let obj_value = self.evaluate(object)?;
                 let index_value = self.evaluate(index)?;
                 let new_value = self.evaluate(value)?;
+                self.set_index(&obj_value, &index_value, new_value.clone())?;
+                Ok(new_value)
+            }
 
-                // Check if it's an array
-                if let Some(array) = obj_value.downcast_ref::<AoiArray>() {
-                    let idx = if let Some(&i) = index_value.downcast_ref::<f64>() {
-                        i as usize
-                    } else if let Some(&i) = index_value.downcast_ref::<i64>() {
-                        i as usize
-                    } else {
-                        return Err("Array index must be a number.".to_string());
+            Expr::Get { object, name } => {
+                let obj_value = self.evaluate(object)?;
+                if let Some(enumeration) = obj_value.downcast_ref::<AoiEnum>() {
+                    let variant = enumeration
+                        .variants
+                        .iter()
+                        .find(|v| v.name.lexeme == name.lexeme)
+                        .ok_or_else(|| format!("Enum '{}' has no variant '{}'.", enumeration.name, name.lexeme))?;
+                    if variant.fields.is_empty() {
+                        return Ok(Arc::new(EnumValue {
+                            enum_name: enumeration.name.clone(),
+                            variant: name.lexeme.clone(),
+                            fields: Vec::new(),
+                            payload: Vec::new(),
+                        }));
+                    }
+                    return Ok(Arc::new(EnumConstructor {
+                        enum_name: enumeration.name.clone(),
+                        variant: name.lexeme.clone(),
+                        fields: variant.fields.iter().map(|f| f.lexeme.clone()).collect(),
+                    }));
+                }
+                if let Some(value) = obj_value.downcast_ref::<EnumValue>() {
+                    let position = value
+                        .fields
+                        .iter()
+                        .position(|f| *f == name.lexeme)
+                        .ok_or_else(|| format!("{}.{} has no field '{}'.", value.enum_name, value.variant, name.lexeme))?;
+                    return Ok(value.payload[position].clone());
+                }
+                if let Some(method) = builtins::method(&obj_value, &name.lexeme) {
+                    return Ok(Arc::new(BoundMethod {
+                        receiver: obj_value.clone(),
+                        method,
+                    }));
+                }
+                Err(format!("Cannot read '{}' of {}.", name.lexeme, self.stringify(&obj_value)))
+            }
+
+            Expr::ParallelAssign { targets, values } => {
+                // Every value is computed before any target changes, so `a, b = b, a` swaps
+                let mut computed = Vec::new();
+                for value in values {
+                    computed.push(self.evaluate(value)?);
+                }
+                if computed.len() == 1 && targets.len() > 1 {
+                    let Some(array) = computed[0].downcast_ref::<AoiArray>() else {
+                        return Err(format!("Cannot destructure {}: not an array.", self.stringify(&computed[0])));
                     };
+                    let items = array.elements.borrow().clone();
+                    self.check_unpack_length(targets.len(), false, items.len())?;
+                    computed = items;
+                }
 
-                    if array.set(idx, new_value.clone()) {
-                        Ok(new_value)
-                    } else {
-                        Err(format!("Array index out of bounds: {} >= {}", idx, array.len()))
+                for (target, value) in targets.iter().zip(computed) {
+                    self.assign_target(target, value)?;
+                }
+                Ok(Arc::new(()))
+            }
+
+            Expr::Range { start, end, inclusive } => {
+                let start_value = self.evaluate(start)?;
+                let end_value = self.evaluate(end)?;
+                Ok(Arc::new(AoiRange {
+                    start: self.integer_value(&start_value, "Range start")?,
+                    end: self.integer_value(&end_value, "Range end")?,
+                    inclusive: *inclusive,
+                }))
+            }
+
+            Expr::Slice { object, start, end, step } => {
+                let obj_value = self.evaluate(object)?;
+                let mut bound = |part: &Option<Box<Expr>>, what: &str| -> Result<Option<i64>, String> {
+                    match part {
+                        Some(expr) => {
+                            let value = self.evaluate(expr)?;
+                            Ok(Some(self.integer_value(&value, what)?))
+                        }
+                        None => Ok(None),
                     }
+                };
+                let start = bound(start, "Slice start")?;
+                let end = bound(end, "Slice end")?;
+                let step = bound(step, "Slice step")?.unwrap_or(1);
+
+                if let Some(array) = obj_value.downcast_ref::<AoiArray>() {
+                    self.slice_array(array, start, end, step)
+                } else if let Some(s) = obj_value.downcast_ref::<String>() {
+                    Ok(Arc::new(self.slice_string(s, start, end, step)?))
                 } else {
-                    Err("Cannot index non-array value.".to_string())
+                    Err("Cannot slice non-array value.".to_string())
                 }
             }
 
+            Expr::Match { subject, arms } => {
+                let value = self.evaluate(subject)?;
+                let (arm, environment) = self.select_arm(&value, arms)?;
+
+                let previous = self.environment.clone();
+                self.environment = environment;
+                let result = self.evaluate(&arm.body);
+                self.environment = previous;
+                result
+            }
+
             Expr::Call { callee, arguments } => {
                 let function_value = self.evaluate(callee)?;
-            
-                let function = function_value
-                    .downcast_ref::<Function>()
-                    .ok_or_else(|| "Runtime error: Expected function, found unsupported type.".to_string())?;
-            
+
                 let mut args = Vec::new();
                 for arg in arguments {
                     args.push(self.evaluate(arg)?);
                 }
-            
-                let result = self.call_function(function, args)?;
-                
-                //  Ensure result is properly unwrapped
-                if let Some(value) = result.downcast_ref::<i64>() {
-                    return Ok(Arc::new(*value)); //  Convert back to expected type
-                }
-                
-                Ok(result) //  Ensure correct type is returned
+
+                self.call_value(&function_value, args)
             }
             
             Expr::Logical {
@@ -434,17 +1866,27 @@ impl Interpreter {
                 right,
             } => {
                 let left_val = self.evaluate(left)?;
-                let left_truthy = self.is_truthy(&left_val);
+
+                //  `??` falls back to the right side only when the left side is nil
+                if operator.token_type == TokenType::QUESTION_QUESTION {
+                    if !self.is_nil(&left_val) {
+                        return Ok(left_val);
+                    }
+                    return self.evaluate(right);
+                }
+
+                // `and`/`or` give back whichever operand decided the result
+                let left_truthy = self.condition(&left_val)?;
 
                 match operator.token_type {
                     TokenType::OR => {
                         if left_truthy {
-                            return Ok(Arc::new(true)); // Ensuring a boolean result
+                            return Ok(left_val);
                         }
                     }
                     TokenType::AND => {
                         if !left_truthy {
-                            return Ok(Arc::new(false)); // Ensuring a boolean result
+                            return Ok(left_val);
                         }
                     }
                     _ => {
@@ -456,7 +1898,10 @@ impl Interpreter {
                 }
 
                 let right_val = self.evaluate(right)?;
-                Ok(Arc::new(self.is_truthy(&right_val))) // Ensure boolean result
+                if self.strict {
+                    self.condition(&right_val)?; // Keeps the result a boolean
+                }
+                Ok(right_val)
             }
 
             Expr::If {
@@ -465,18 +1910,12 @@ impl Interpreter {
                 else_branch,
             } => {
                 let condition_value = self.evaluate(condition)?;
-            
-                // Ensure the condition is treated as a boolean
-                if let Some(condition_bool) = condition_value.downcast_ref::<bool>() {
-                    if *condition_bool {
-                        return self.evaluate(then_branch);
-                    } else if let Some(else_expr) = else_branch {
-                        return self.evaluate(else_expr);
-                    } else {
-                        return Ok(Arc::new(())); // Ensure that the if-expression always returns a value (avoid nil issues)
-                    }
+                if self.condition(&condition_value)? {
+                    self.evaluate(then_branch)
+                } else if let Some(else_expr) = else_branch {
+                    self.evaluate(else_expr)
                 } else {
-                    return Err("Runtime error: Condition must be a boolean.".to_string());
+                    Ok(Arc::new(())) // Ensure that the if-expression always returns a value (avoid nil issues)
                 }
             }
             
@@ -488,23 +1927,7 @@ impl Interpreter {
                 );
             
                 match self.environment.borrow().get(&token) {
-                    Ok(value) => {
-                        if let Some(v) = value.downcast_ref::<f64>() {
-                            Ok(Arc::new(*v))
-                        } else if let Some(v) = value.downcast_ref::<String>() {
-                            Ok(Arc::new(v.clone()))
-                        } else if let Some(v) = value.downcast_ref::<bool>() {
-                            Ok(Arc::new(*v))
-                        } else if value.is::<()>() {
-                            Ok(Arc::new(TokenLiteral::Null)) //  Return `nil` for uninitialized variables
-                        } else if let Some(func) = value.downcast_ref::<Function>() {
-                            Ok(Arc::new(func.clone())) //  Return the function reference
-                        } else if let Some(arr) = value.downcast_ref::<AoiArray>() {
-                            Ok(Arc::new(arr.clone())) //  Return the array reference
-                        } else {
-                            Err("Unsupported type.".to_string())
-                        }
-                    }
+                    Ok(value) => Ok(self.stored_value(&value)), // Unset variables read as `nil`
                     Err(_) => Err(format!("Undefined variable '{}'.", name.name.lexeme)), //  Return `nil` if variable is undefined
                 }
             }
@@ -512,27 +1935,10 @@ impl Interpreter {
 
             Expr::Assign(name, value_expr) => {
                 let value = self.evaluate(value_expr)?;
-                let cloned_value = if let Some(v) = value.downcast_ref::<f64>() {
-                    Arc::new(*v) as Arc<dyn Any + Send + Sync>
-                } else if let Some(v) = value.downcast_ref::<String>() {
-                    Arc::new(v.clone()) as Arc<dyn Any + Send + Sync>
-                } else if let Some(v) = value.downcast_ref::<bool>() {
-                    Arc::new(*v) as Arc<dyn Any + Send + Sync>
-                } else if let Some(v) = value.downcast_ref::<AoiArray>() {
-                    Arc::new(v.clone()) as Arc<dyn Any + Send + Sync>
-                } else {
-                    Arc::new(()) as Arc<dyn Any + Send + Sync>
-                };
+                let cloned_value = self.stored_value(&value);
                 self.environment
                     .borrow_mut()
-                    .assign(
-                        &Token::new(
-                            TokenType::IDENTIFIER,
-                            name.clone(),
-                            TokenLiteral::Identifier(name.clone()),
-                        ),
-                        cloned_value,
-                    )
+                    .assign(name, cloned_value)
                     .map_err(|e| e.to_string())?;
                 Ok(value)
             }
@@ -540,6 +1946,7 @@ impl Interpreter {
                 if let Some(token_literal) = lit.value.downcast_ref::<TokenLiteral>() {
                     match token_literal {
                         TokenLiteral::Number(n) => Ok(Arc::new(*n)),
+                        TokenLiteral::Decimal(d) => Ok(Arc::new(*d)),
                         TokenLiteral::String(s) => Ok(Arc::new(s.clone())),
                         TokenLiteral::Identifier(id) => Ok(Arc::new(id.clone())),
                         TokenLiteral::Boolean(b) => Ok(Arc::new(*b)),
@@ -557,16 +1964,12 @@ impl Interpreter {
 
                 match unary.operator.token_type {
                     TokenType::MINUS => {
-                        if let Some(n) = right.downcast_ref::<f64>() {
-                            return Ok(Arc::new(-*n));
-                        }
-                        Err("Operand must be a number.".to_string())
+                        number::negate(&right).ok_or_else(|| "Operand must be a number.".to_string())
                     }
-                    TokenType::BANG => {
-                        if let Some(b) = right.downcast_ref::<bool>() {
-                            return Ok(Arc::new(!b));
-                        }
-                        Ok(Arc::new(false))
+                    TokenType::BANG => Ok(Arc::new(!self.condition(&right)?)),
+                    TokenType::TILDE => {
+                        let n = self.exact_integer_value(&right, "Operand of '~'")?;
+                        Ok(number::integer(!n))
                     }
                     TokenType::FALSE => Ok(Arc::new(false)),
                     TokenType::TRUE => Ok(Arc::new(true)),
@@ -578,201 +1981,61 @@ impl Interpreter {
                 let right = self.evaluate(&binary.right)?;
 
                 match binary.operator.token_type {
-                    // In your binary operation handler for PLUS
-                TokenType::PLUS => {
-                    // First, try to handle the case where both are f64
-                    if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
-                        return Ok(Arc::new(l + r));
-                    }
-                    
-                    // Add this new case to handle i64 + i64
-                    if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<i64>()) {
-                        return Ok(Arc::new(l + r));
-                    }
-                    
-                    // Add conversion between i64 and f64
-                    if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<f64>()) {
-                        return Ok(Arc::new(*l as f64 + r));
-                    }
-                    
-                    if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<i64>()) {
-                        return Ok(Arc::new(l + *r as f64));
-                    }
-                    
-                    // String handling remains the same...
-                    if let (Some(l), Some(r)) = (
-                        left.downcast_ref::<String>(),
-                        right.downcast_ref::<String>(),
-                    ) {
-                        return Ok(Arc::new(format!("{}{}", l, r)));
-                    }
-                    
-                    // Mixed string handling remains the same...
-                    if let Some(l) = left.downcast_ref::<String>() {
-                        return Ok(Arc::new(format!("{}{}", l, self.stringify(&right))));
-                    }
-                    if let Some(r) = right.downcast_ref::<String>() {
-                        return Ok(Arc::new(format!("{}{}", self.stringify(&left), r)));
-                    }
-                    
-                    Err("Operands must be two numbers or two strings.".to_string())
-                }
-
-                    TokenType::MINUS => {
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(l - r));
-                        }
-                        
-                        // Add this new case to handle i64 + i64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l - r));
-                        }
-                        
-                        // Add conversion between i64 and f64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(*l as f64 - r));
-                        }
-                        
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l - *r as f64));
-                        }if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(l - r));
-                        }
-                        
-                        // Add this new case to handle i64 + i64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l * r));
-                        }
-                        
-                        // Add conversion between i64 and f64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(*l as f64 * r));
-                        }
-                        
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l * *r as f64));
-                        }
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l - r));
-                        }
-                        Err("Operands must be numbers.".to_string())
-                    }
-                    TokenType::STAR => {
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(l * r));
-                        }
-                        
-                        // Add this new case to handle i64 + i64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l * r));
-                        }
-                        
-                        // Add conversion between i64 and f64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(*l as f64 * r));
-                        }
-                        
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l * *r as f64));
-                        }
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l * r));
-                        }
-                        Err("Operands must be numbers.".to_string())
-                    }
-                    TokenType::SLASH => {
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(l / r));
-                        }
-                        
-                        // Add this new case to handle i64 + i64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l / r));
-                        }
-                        
-                        // Add conversion between i64 and f64
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<i64>(), right.downcast_ref::<f64>()) {
-                            return Ok(Arc::new(*l as f64 / r));
-                        }
-                        
-                        if let (Some(l), Some(r)) = (left.downcast_ref::<f64>(), right.downcast_ref::<i64>()) {
-                            return Ok(Arc::new(l / *r as f64));
-                        }
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            if *r == 0.0 {
-                                return Err("Division by zero.".to_string());
-                            }
-                            return Ok(Arc::new(l / r));
+                    TokenType::PLUS => {
+                        if let Some(result) = number::arithmetic(&binary.operator.token_type, &left, &right) {
+                            return result;
                         }
-                        Err("Operands must be numbers.".to_string())
-                    }
 
-                    TokenType::EQUAL_EQUAL => {
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l == r));
-                        }
                         if let (Some(l), Some(r)) = (
                             left.downcast_ref::<String>(),
                             right.downcast_ref::<String>(),
                         ) {
-                            return Ok(Arc::new(l == r));
+                            return Ok(Arc::new(format!("{}{}", l, r)));
                         }
-                        Err("Operands must be two numbers or two strings.".to_string())
-                    }
-                    TokenType::BANG_EQUAL => {
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l != r));
+
+                        // A string joined with any other value
+                        if let Some(l) = left.downcast_ref::<String>() {
+                            return Ok(Arc::new(format!("{}{}", l, self.stringify(&right))));
                         }
-                        if let (Some(l), Some(r)) = (
-                            left.downcast_ref::<String>(),
-                            right.downcast_ref::<String>(),
-                        ) {
-                            return Ok(Arc::new(l != r));
+                        if let Some(r) = right.downcast_ref::<String>() {
+                            return Ok(Arc::new(format!("{}{}", self.stringify(&left), r)));
                         }
+
                         Err("Operands must be two numbers or two strings.".to_string())
                     }
 
-                    TokenType::GREATER => {
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l > r));
-                        }
-                        Err("Operands must be numbers.".to_string())
-                    }
-                    TokenType::GREATER_EQUAL => {
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l >= r));
-                        }
-                        Err("Operands must be numbers.".to_string())
-                    }
-                    TokenType::LESS => {
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l < r));
-                        }
-                        Err("Operands must be numbers.".to_string())
-                    }
-                    TokenType::LESS_EQUAL => {
-                        if let (Some(l), Some(r)) =
-                            (left.downcast_ref::<f64>(), right.downcast_ref::<f64>())
-                        {
-                            return Ok(Arc::new(l <= r));
-                        }
-                        Err("Operands must be numbers.".to_string())
+                    TokenType::MINUS
+                    | TokenType::STAR
+                    | TokenType::SLASH
+                    | TokenType::PERCENT
+                    | TokenType::STAR_STAR => number::arithmetic(&binary.operator.token_type, &left, &right)
+                        .unwrap_or_else(|| Err("Operands must be numbers.".to_string())),
+
+                    TokenType::AMPERSAND
+                    | TokenType::PIPE
+                    | TokenType::CARET
+                    | TokenType::LESS_LESS
+                    | TokenType::GREATER_GREATER => self.bitwise(&binary.operator, &left, &right),
+
+                    TokenType::EQUAL_EQUAL => Ok(Arc::new(self.values_equal(&left, &right)?)),
+                    TokenType::BANG_EQUAL => Ok(Arc::new(!self.values_equal(&left, &right)?)),
+
+                    TokenType::GREATER
+                    | TokenType::GREATER_EQUAL
+                    | TokenType::LESS
+                    | TokenType::LESS_EQUAL => {
+                        let ordering = if number::is_number(&left) && number::is_number(&right) {
+                            number::compare(&left, &right) // NaN is unordered: every comparison is false
+                        } else {
+                            Some(self.compare_values(&left, &right)?)
+                        };
+                        let result = match binary.operator.token_type {
+                            TokenType::GREATER => ordering == Some(Ordering::Greater),
+                            TokenType::GREATER_EQUAL => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                            TokenType::LESS => ordering == Some(Ordering::Less),
+                            _ => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                        };
+                        Ok(Arc::new(result))
                     }
                     _ => Err("Unknown binary operator.".to_string()),
                 }
@@ -780,19 +2043,78 @@ impl Interpreter {
         }
     }
 
-    fn stringify(&self, value: &Arc<dyn Any + Send + Sync>) -> String {
+    /// The value of a variable visible from the current scope, for `{name}` placeholders.
+    pub(crate) fn variable(&self, name: &str) -> Option<Arc<dyn Any + Send + Sync>> {
+        let token = Token::new(TokenType::IDENTIFIER, name.to_string(), TokenLiteral::Identifier(name.to_string()));
+        let value = self.environment.borrow().get(&token).ok()?;
+        Some(self.stored_value(&value))
+    }
+
+    /// What `help(value)` shows: the signature of a function and its doc comment.
+    pub(crate) fn help_text(&self, value: &Arc<dyn Any + Send + Sync>) -> Option<String> {
+        let native = value
+            .downcast_ref::<NativeFunction>()
+            .or_else(|| value.downcast_ref::<BoundMethod>().map(|bound| &bound.method));
+        if let Some(native) = native {
+            let arity = native.arity();
+            let plural = if arity == "1" || arity == "at least 1" { "" } else { "s" };
+            return Some(format!("{} (built-in, takes {} argument{})", native.name, arity, plural));
+        }
+        let function = value.downcast_ref::<Function>()?;
+        let doc = function.doc.as_deref().unwrap_or("No documentation.");
+        let doc: Vec<String> = doc.lines().map(|line| format!("    {}", line).trim_end().to_string()).collect();
+        Some(format!("{}\n{}", function.signature, doc.join("\n")))
+    }
+
+    /// Adds text to the program output, as `write` does.
+    pub(crate) fn print(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    pub(crate) fn stringify(&self, value: &Arc<dyn Any + Send + Sync>) -> String {
         if let Some(v) = value.downcast_ref::<i64>() {
             return v.to_string();
         } else if let Some(v) = value.downcast_ref::<f64>() {
             return v.to_string();
+        } else if let Some(v) = value.downcast_ref::<BigInt>() {
+            return v.to_string();
+        } else if let Some(v) = value.downcast_ref::<Decimal>() {
+            return v.to_string();
         } else if let Some(v) = value.downcast_ref::<String>() {
             return v.clone();
+        } else if let Some(v) = value.downcast_ref::<bool>() {
+            return v.to_string();
         } else if let Some(arr) = value.downcast_ref::<AoiArray>() {
             let elements = arr.elements.borrow();
             let stringified: Vec<String> = elements.iter()
                 .map(|e| self.stringify(e))
                 .collect();
             return format!("[{}]", stringified.join(", "));
+        } else if let Some(range) = value.downcast_ref::<AoiRange>() {
+            let operator = if range.inclusive { "..=" } else { ".." };
+            return format!("{}{}{}", range.start, operator, range.end);
+        } else if let Some(function) = value.downcast_ref::<Function>() {
+            return format!("<fn {}>", function.name);
+        } else if let Some(native) = value.downcast_ref::<NativeFunction>() {
+            return format!("<native fn {}>", native.name);
+        } else if let Some(bound) = value.downcast_ref::<BoundMethod>() {
+            return format!("<method {}>", bound.method.name);
+        } else if let Some(generator) = value.downcast_ref::<Generator>() {
+            return format!("<generator {}>", generator.name);
+        } else if let Some(task) = value.downcast_ref::<Task>() {
+            return format!("<task {}>", task.name);
+        } else if value.is::<Channel>() {
+            return "<channel>".to_string();
+        } else if let Some(enumeration) = value.downcast_ref::<AoiEnum>() {
+            return format!("<enum {}>", enumeration.name);
+        } else if let Some(constructor) = value.downcast_ref::<EnumConstructor>() {
+            return format!("<constructor {}.{}>", constructor.enum_name, constructor.variant);
+        } else if let Some(value) = value.downcast_ref::<EnumValue>() {
+            if value.payload.is_empty() {
+                return format!("{}.{}", value.enum_name, value.variant);
+            }
+            let payload: Vec<String> = value.payload.iter().map(|p| self.stringify(p)).collect();
+            return format!("{}.{}({})", value.enum_name, value.variant, payload.join(", "));
         } else if value.downcast_ref::<()>().is_some() {
             return "nil".to_string();
         }
@@ -0,0 +1,477 @@
+use crate::expr::{Expr, Pattern};
+use crate::format;
+use crate::stmt::Stmt;
+use crate::token::{Token, TokenLiteral};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Static pass behind `aoi lint`. Like the resolver it follows the interpreter's
+// scoping rules, but it looks for code that runs and is still probably wrong.
+
+/// How findings of a rule are reported.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow, // Not reported
+    Warn,
+    Deny, // Reported as an error, so `lint` fails
+}
+
+/// Every rule, with what it reports.
+pub const RULES: &[(&str, &str)] = &[
+    ("unused-variable", "Variables that are never read"),
+    ("unused-parameter", "Function parameters that are never read"),
+    ("shadowing", "Declarations that hide a name from an enclosing scope"),
+    ("assignment-in-condition", "`=` used as the condition of `if` or a loop"),
+    ("unreachable-code", "Statements after `return`"),
+    ("self-assignment", "Assigning a variable to itself"),
+    ("constant-condition", "Conditions that never change, other than `while (true)`"),
+    ("empty-block", "Blocks with nothing in them"),
+];
+
+pub struct Diagnostic {
+    pub line: usize,
+    pub rule: &'static str,
+    pub message: String,
+    pub denied: bool,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.denied { "Error" } else { "Warning" };
+        write!(f, "[line {}] {}: {} [{}]", self.line, kind, self.message, self.rule)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Variable,
+    Parameter,
+    Declaration, // Functions and enums, which are not reported when unused
+}
+
+struct Binding {
+    line: usize,
+    kind: Kind,
+    used: bool,
+}
+
+pub struct Linter {
+    levels: HashMap<&'static str, Level>,
+    scopes: Vec<HashMap<String, Binding>>,
+    late: HashSet<String>, // Names read before their declaration, e.g. globals used in earlier functions
+    diagnostics: Vec<Diagnostic>,
+    line: usize, // Line of the most recent token, for statements without one
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Linter {
+            levels: RULES.iter().map(|&(rule, _)| (rule, Level::Warn)).collect(),
+            scopes: vec![HashMap::new()], // Global scope
+            late: HashSet::new(),
+            diagnostics: Vec::new(),
+            line: 0,
+        }
+    }
+
+    /// Sets how one rule is reported, failing for names not in `RULES`.
+    pub fn set_level(&mut self, rule: &str, level: Level) -> Result<(), String> {
+        match RULES.iter().find(|&&(name, _)| name == rule) {
+            Some(&(name, _)) => {
+                self.levels.insert(name, level);
+                Ok(())
+            }
+            None => Err(format!("Unknown lint rule '{}'.", rule)),
+        }
+    }
+
+    /// Checks a whole program, returning its findings in line order.
+    pub fn lint(mut self, statements: &[Stmt]) -> Vec<Diagnostic> {
+        self.statements(statements);
+        self.end_scope();
+        self.diagnostics
+            .sort_by(|a, b| a.line.cmp(&b.line).then_with(|| a.message.cmp(&b.message)));
+        self.diagnostics
+    }
+
+    fn report(&mut self, rule: &'static str, line: usize, message: String) {
+        let level = self.levels.get(rule).copied().unwrap_or(Level::Warn);
+        if level == Level::Allow {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            line,
+            rule,
+            message,
+            denied: level == Level::Deny,
+        });
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("linter always has a scope");
+        for (name, binding) in scope {
+            if !binding.used && !self.late.contains(&name) {
+                self.report_unused(&name, &binding);
+            }
+        }
+    }
+
+    // A leading `_` marks a name as unused on purpose
+    fn report_unused(&mut self, name: &str, binding: &Binding) {
+        if name.starts_with('_') {
+            return;
+        }
+        match binding.kind {
+            Kind::Variable => {
+                self.report("unused-variable", binding.line, format!("Variable '{}' is never used.", name))
+            }
+            Kind::Parameter => {
+                self.report("unused-parameter", binding.line, format!("Parameter '{}' is never used.", name))
+            }
+            Kind::Declaration => {}
+        }
+    }
+
+    fn declare(&mut self, name: &Token, kind: Kind) {
+        self.line = name.line;
+        let (scope, enclosing) = self.scopes.split_last().expect("linter always has a scope");
+        let shadowed = enclosing.iter().rev().find_map(|scope| scope.get(&name.lexeme)).map(|outer| outer.line);
+        let replaced = scope.contains_key(&name.lexeme);
+        if let Some(line) = shadowed.filter(|_| !replaced) {
+            let message = format!("'{}' shadows the declaration on line {}.", name.lexeme, line);
+            self.report("shadowing", name.line, message);
+        }
+
+        let binding = Binding {
+            line: name.line,
+            kind,
+            used: false,
+        };
+        let scope = self.scopes.last_mut().expect("linter always has a scope");
+        if let Some(previous) = scope.insert(name.lexeme.clone(), binding) {
+            if !previous.used {
+                self.report_unused(&name.lexeme, &previous);
+            }
+        }
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => self.declare(name, Kind::Variable),
+            Pattern::Array { elements, rest } => {
+                for element in elements {
+                    self.declare_pattern(element);
+                }
+                if let Some(rest) = rest {
+                    self.declare(rest, Kind::Variable);
+                }
+            }
+            Pattern::Variant { fields, .. } => {
+                for field in fields {
+                    self.declare_pattern(field);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } => {}
+        }
+    }
+
+    fn read(&mut self, name: &Token) {
+        self.line = name.line;
+        self.read_name(&name.lexeme);
+    }
+
+    fn read_name(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.get_mut(name) {
+                binding.used = true;
+                return;
+            }
+        }
+        self.late.insert(name.to_string());
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for (position, stmt) in statements.iter().enumerate() {
+            self.statement(stmt);
+            if let Stmt::Return { keyword, .. } = stmt {
+                if position + 1 < statements.len() {
+                    let message = "Statements after this 'return' are never run.".to_string();
+                    self.report("unreachable-code", keyword.line, message);
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression } => self.expression(expression),
+            Stmt::Print { expressions, separator, .. } => {
+                for expression in expressions.iter().chain(separator) {
+                    self.expression(expression);
+                }
+            }
+            Stmt::Var { name, initializer, .. } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name, Kind::Variable);
+            }
+            Stmt::Destructure { pattern, initializer } => {
+                self.expression(initializer);
+                self.declare_pattern(pattern);
+            }
+            Stmt::Const { name, initializer, .. } => {
+                self.expression(initializer);
+                self.declare(name, Kind::Variable);
+            }
+            Stmt::Block(statements) => {
+                if statements.is_empty() {
+                    let line = self.line;
+                    self.report("empty-block", line, "Empty block.".to_string());
+                }
+                self.begin_scope();
+                self.statements(statements);
+                self.end_scope();
+            }
+            Stmt::Test { body, .. } => {
+                self.begin_scope();
+                self.statements(body);
+                self.end_scope();
+            }
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.condition(keyword, condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::While { keyword, condition, body } => {
+                self.condition(keyword, condition);
+                self.statement(body);
+            }
+            Stmt::Input { name } => self.line = name.line,
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.begin_scope();
+                if let Some(initializer) = initializer {
+                    self.statement(initializer);
+                }
+                for expression in condition.iter().chain(increment) {
+                    self.expression(expression);
+                }
+                self.statement(body);
+                self.end_scope();
+            }
+            Stmt::ForIn {
+                index,
+                variable,
+                iterable,
+                body,
+            } => {
+                self.expression(iterable);
+                self.begin_scope();
+                if let Some(index) = index {
+                    self.declare(index, Kind::Variable);
+                }
+                self.declare(variable, Kind::Variable);
+                self.statement(body);
+                self.end_scope();
+            }
+            Stmt::Function { name, params, body, .. } => {
+                self.declare(name, Kind::Declaration);
+                self.begin_scope();
+                for param in params {
+                    self.declare(param, Kind::Parameter);
+                }
+                self.statements(body);
+                self.end_scope();
+            }
+            Stmt::Return { keyword, value } | Stmt::Yield { keyword, value } => {
+                self.line = keyword.line;
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::Enum { name, .. } => self.declare(name, Kind::Declaration),
+            Stmt::Match { subject, arms } => {
+                self.expression(subject);
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    self.statement(&arm.body);
+                    self.end_scope();
+                }
+            }
+        }
+    }
+
+    // The condition of `if` or `while`
+    fn condition(&mut self, keyword: &Token, condition: &Expr) {
+        self.line = keyword.line;
+        let inner = ungrouped(condition);
+        if matches!(inner, Expr::Assign(..) | Expr::IndexAssign { .. } | Expr::ParallelAssign { .. }) {
+            let message = "Assignment used as a condition; did you mean '=='?".to_string();
+            self.report("assignment-in-condition", keyword.line, message);
+        }
+        // `while (true)` is how infinite loops are written
+        let endless = keyword.lexeme != "if" && is_true(inner);
+        if is_constant(inner) && !endless {
+            let message = format!("Condition of '{}' is always the same.", keyword.lexeme);
+            self.report("constant-condition", keyword.line, message);
+        }
+        self.expression(condition);
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable(variable) => self.read(&variable.name),
+            Expr::Assign(name, value) => {
+                self.line = name.line;
+                if let Expr::Variable(variable) = ungrouped(value) {
+                    if variable.name.lexeme == name.lexeme {
+                        let message = format!("'{}' is assigned to itself.", name.lexeme);
+                        self.report("self-assignment", name.line, message);
+                    }
+                }
+                self.expression(value);
+            }
+            Expr::Binary(binary) => {
+                self.line = binary.operator.line;
+                self.expression(&binary.left);
+                self.expression(&binary.right);
+            }
+            Expr::Grouping(group) => self.expression(&group.expression),
+            Expr::Unary(unary) => {
+                self.line = unary.operator.line;
+                self.expression(&unary.right);
+            }
+            Expr::Literal(_) => {}
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expression(else_branch);
+                }
+            }
+            Expr::Logical { left, operator, right } => {
+                self.line = operator.line;
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call { callee, arguments } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                // `format("{total}")` reads `total`
+                if let (Expr::Variable(function), Some(Expr::Literal(template))) = (callee.as_ref(), arguments.first()) {
+                    if function.name.lexeme == "format" {
+                        if let Some(TokenLiteral::String(template)) = template.value.downcast_ref::<TokenLiteral>() {
+                            for name in format::named_placeholders(template) {
+                                self.read_name(name);
+                            }
+                        }
+                    }
+                }
+            }
+            Expr::Array { elements } => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expr::Index { object, index } | Expr::SafeIndex { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::IndexAssign { object, index, value } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+            }
+            Expr::ParallelAssign { targets, values } => {
+                for value in values {
+                    self.expression(value);
+                }
+                for target in targets {
+                    match target {
+                        Expr::Variable(variable) => self.line = variable.name.line, // Written, not read
+                        _ => self.expression(target),
+                    }
+                }
+            }
+            Expr::Get { object, name } => {
+                self.line = name.line;
+                self.expression(object);
+            }
+            Expr::Range { start, end, .. } => {
+                self.expression(start);
+                self.expression(end);
+            }
+            Expr::Slice { object, start, end, step } => {
+                self.expression(object);
+                for part in [start, end, step].into_iter().flatten() {
+                    self.expression(part);
+                }
+            }
+            Expr::Match { subject, arms } => {
+                self.expression(subject);
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    self.expression(&arm.body);
+                    self.end_scope();
+                }
+            }
+        }
+    }
+}
+
+fn ungrouped(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Grouping(group) => ungrouped(&group.expression),
+        other => other,
+    }
+}
+
+// Made only of literals and operators, so it has the same value every time
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Grouping(group) => is_constant(&group.expression),
+        Expr::Unary(unary) => is_constant(&unary.right),
+        Expr::Binary(binary) => is_constant(&binary.left) && is_constant(&binary.right),
+        Expr::Logical { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+fn is_true(expr: &Expr) -> bool {
+    let Expr::Literal(literal) = expr else {
+        return false;
+    };
+    matches!(literal.value.downcast_ref::<TokenLiteral>(), Some(TokenLiteral::Boolean(true)))
+        || literal.value.downcast_ref::<bool>() == Some(&true)
+}
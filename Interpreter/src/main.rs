@@ -1,16 +1,37 @@
 mod error;
 mod token;
 mod astprinter;
+mod builtins;
+mod coroutine;
+mod docgen;
 mod expr;
+mod format;
+mod generator;
 mod interpreter;
+mod lint;
+mod number;
+mod optimizer;
 mod parser;
 mod stmt;
+mod tasks;
+mod testrunner;
+mod typechecker;
+mod types;
 mod environment;
+mod resolver;
 
 use std::env;
 use std::fs;
-use token::Tokensizer;
+use std::io::Write;
+use std::process;
+use rust_decimal::prelude::ToPrimitive;
+use token::{TokenLiteral, TokenType, Tokensizer};
+use astprinter::AstPrinter;
 use interpreter::Interpreter;
+use optimizer::Optimizer;
+use resolver::Resolver;
+use stmt::Stmt;
+use typechecker::TypeChecker;
 
 use axum::{
     body::Bytes,
@@ -22,41 +43,234 @@ use axum::{
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 
+// Command-line flags that change how a program is checked and run
+#[derive(Default)]
+struct Options {
+    deterministic: bool, // `--deterministic`: tasks run in spawn order
+    strict: bool,        // `--strict`: conditions must be booleans
+    sandboxed: bool,     // Set for the server: no `env()`, `ewrite` joins the output
+    args: Vec<String>,   // Arguments after the file name, seen by the program as `args`
+    ast: bool,           // `--ast`: print the optimized syntax tree instead of running
+}
+
+impl Options {
+    // Reads the flags before the file name, returning the remaining arguments
+    fn parse(args: &[String]) -> Result<(Options, &[String]), String> {
+        let mut options = Options::default();
+        let mut rest = args;
+        while let Some(flag) = rest.first().filter(|arg| arg.starts_with("--")) {
+            match flag.as_str() {
+                "--deterministic" => options.deterministic = true,
+                "--strict" => options.strict = true,
+                "--ast" => options.ast = true,
+                _ => return Err(format!("Unknown option '{}'.", flag)),
+            }
+            rest = &rest[1..];
+        }
+        Ok((options, rest))
+    }
+}
+
 async fn run_handler(bytes: Bytes) -> impl IntoResponse {
     let code = String::from_utf8(bytes.to_vec()).unwrap();
-    let result = run_code(&code);
-    (StatusCode::OK, result)
+    let options = Options {
+        deterministic: true, // Reproducible output for the same program
+        sandboxed: true,
+        ..Options::default()
+    };
+    let (output, _) = run_code(&code, &options);
+    (StatusCode::OK, output)
+}
+
+async fn check_handler(bytes: Bytes) -> impl IntoResponse {
+    let code = String::from_utf8(bytes.to_vec()).unwrap();
+    let (output, _) = check_code(&code, &Options::default());
+    (StatusCode::OK, output)
+}
+
+// Parses, resolves and type-checks a program, returning every error found
+fn compile(source: &str, options: &Options) -> Result<Vec<Stmt>, String> {
+    let mut tokenizer = Tokensizer::new(source.to_string());
+    let tokens = tokenizer.tokenize().map_err(|errors| errors.join("\n"))?;
+    let mut parser = parser::Parser::new(tokens);
+
+    let statements = parser.parse().map_err(|errors| errors.join("\n"))?;
+
+    let errors = Resolver::new().resolve(&statements);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let errors = TypeChecker::new().strict(options.strict).check(&statements);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    Ok(Optimizer::new().strict(options.strict).optimize(statements))
+}
+
+// Reports problems in a program without running it. Like `run_code`, gives
+// the text to show and the process exit status.
+fn check_code(source: &str, options: &Options) -> (String, i32) {
+    // The passes over the tree recurse, which needs more stack than the main
+    // thread or a server worker has
+    coroutine::on_large_stack(|| match compile(source, options) {
+        Ok(_) => ("No errors found.".to_string(), 0),
+        Err(errors) => (errors, 1),
+    })
 }
 
+// The syntax tree the interpreter would run, after optimization
+fn ast_code(source: &str, options: &Options) -> (String, i32) {
+    coroutine::on_large_stack(|| match compile(source, options) {
+        Ok(statements) => (AstPrinter.print(&statements), 0),
+        Err(errors) => (errors, 1),
+    })
+}
 
+// `lint [--allow rule] [--deny rule] [--strict] <files...>`: reports errors
+// like `check`, then the findings of each lint rule. Returns the exit status.
+fn lint_files(args: &[String]) -> i32 {
+    let mut levels = Vec::new();
+    let mut strict = false;
+    let mut files = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let level = match arg.as_str() {
+            "--allow" => lint::Level::Allow,
+            "--warn" => lint::Level::Warn,
+            "--deny" => lint::Level::Deny,
+            "--strict" => {
+                strict = true;
+                continue;
+            }
+            "--rules" => {
+                for (rule, description) in lint::RULES {
+                    println!("{:<24} {}", rule, description);
+                }
+                return 0;
+            }
+            flag if flag.starts_with("--") => {
+                eprintln!("Unknown option '{}'.", flag);
+                return 2;
+            }
+            _ => {
+                files.push(arg);
+                continue;
+            }
+        };
+        let Some(rule) = rest.next() else {
+            eprintln!("Missing rule name after '{}'.", arg);
+            return 2;
+        };
+        levels.push((rule.as_str(), level));
+    }
+    if files.is_empty() {
+        eprintln!("Missing file name.");
+        return 2;
+    }
 
-fn run_code(source: &str) -> String {
+    let (mut warnings, mut errors) = (0, 0);
+    for file in &files {
+        let source = fs::read_to_string(file).expect("Failed to read file");
+        let mut linter = lint::Linter::new();
+        for &(rule, level) in &levels {
+            if let Err(message) = linter.set_level(rule, level) {
+                eprintln!("{} See `lint --rules`.", message);
+                return 2;
+            }
+        }
+
+        if files.len() > 1 {
+            println!("{}:", file);
+        }
+        let parsed = Tokensizer::new(source)
+            .tokenize()
+            .and_then(|tokens| parser::Parser::new(tokens).parse());
+        let statements = match parsed {
+            Ok(statements) => statements,
+            Err(problems) => {
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+                errors += problems.len();
+                continue;
+            }
+        };
+        let mut problems = Resolver::new().resolve(&statements);
+        problems.extend(TypeChecker::new().strict(strict).check(&statements));
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        errors += problems.len();
+        for diagnostic in linter.lint(&statements) {
+            println!("{}", diagnostic);
+            if diagnostic.denied {
+                errors += 1;
+            } else {
+                warnings += 1;
+            }
+        }
+    }
+
+    if warnings + errors == 0 {
+        println!("No problems found.");
+    } else {
+        println!("{} warning{}, {} error{}.", warnings, if warnings == 1 { "" } else { "s" }, errors, if errors == 1 { "" } else { "s" });
+    }
+    if errors > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn run_code(source: &str, options: &Options) -> (String, i32) {
+    // Deep recursion in Aoi code needs far more stack than the main thread has
+    coroutine::on_large_stack(|| run_program(source, options))
+}
+
+// Rejects programs that use what this interpreter does not allow
+fn check_limits(source: &str) -> Result<(), String> {
     // Disallow "scan" keyword
     if source.contains("scan") {
-        return "Error: Usage of 'scan' keyword is not allowed.".to_string();
+        return Err("Error: Usage of 'scan' keyword is not allowed.".to_string());
     }
 
-    // Disallow numeric values > 148
-    let number_check = regex::Regex::new(r"\b\d+\b").unwrap();
-    for cap in number_check.captures_iter(source) {
-        if let Ok(n) = cap[0].parse::<u32>() {
-            if n > 148 {
-                return format!("Error: Numeric value '{}' exceeds the limit of 148.", n);
-            }
+    // Disallow numeric values > 148, however the literal is written (`0xFF`, `1e9`, `1_000`, `500d`).
+    // Only literals in the code count, not digits in comments or strings. A source
+    // that does not tokenize is left for `compile` to report.
+    let Ok(tokens) = Tokensizer::new(source.to_string()).tokenize() else {
+        return Ok(());
+    };
+    for token in tokens.iter().filter(|token| token.token_type == TokenType::NUMBER) {
+        let value = match &token.literal {
+            Some(TokenLiteral::Number(n)) => *n,
+            Some(TokenLiteral::Decimal(d)) => d.to_f64().unwrap_or(f64::INFINITY),
+            _ => continue,
+        };
+        if value > 148.0 {
+            return Err(format!("Error: Numeric value '{}' exceeds the limit of 148.", token.lexeme));
         }
     }
+    Ok(())
+}
 
-    let mut tokenizer = Tokensizer::new(source.to_string());
-    let tokens = tokenizer.tokenize();
-    let mut parser = parser::Parser::new(tokens);
+fn run_program(source: &str, options: &Options) -> (String, i32) {
+    if let Err(error) = check_limits(source) {
+        return (error, 1);
+    }
 
-    match parser.parse() {
-        Some(statements) => {
+    match compile(source, options) {
+        Ok(statements) => {
             let mut interpreter = Interpreter::new();
-            let result = interpreter.interpret(&statements);
-            result
+            interpreter.set_deterministic(options.deterministic);
+            interpreter.set_strict(options.strict);
+            interpreter.set_sandboxed(options.sandboxed);
+            interpreter.set_args(options.args.clone());
+            let output = interpreter.interpret(&statements);
+            (output, interpreter.exit_status())
         }
-        None => "Parsing failed due to syntax errors.".to_string(),
+        Err(errors) => (errors, 1),
     }
 }
 
@@ -70,21 +284,71 @@ async fn main() {
 
         let app = Router::new()
             .route("/", axum::routing::get(|| async { "Aoi interpreter server is running" }))
-            .route("/run", post(run_handler));
+            .route("/run", post(run_handler))
+            .route("/check", post(check_handler));
 
         let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
         let listener = TcpListener::bind(addr).await.unwrap();
         axum::serve(listener, app).await.unwrap();
+    } else if args.len() >= 2 && args[1] == "test" {
+        // Files and directories to search, the current directory by default
+        let paths = if args.len() > 2 { args[2..].to_vec() } else { vec![".".to_string()] };
+        process::exit(testrunner::run(&paths));
+    } else if args.len() >= 2 && args[1] == "lint" {
+        process::exit(coroutine::on_large_stack(|| lint_files(&args[2..])));
+    } else if args.len() >= 2 && args[1] == "doc" {
+        // Markdown by default, `--html` for a standalone page
+        let html = args.get(2).is_some_and(|arg| arg == "--html");
+        let Some(filename) = args.get(if html { 3 } else { 2 }) else {
+            eprintln!("Missing file name.");
+            process::exit(2);
+        };
+        let source = fs::read_to_string(filename).expect("Failed to read file");
+        let format = if html { docgen::Format::Html } else { docgen::Format::Markdown };
+        match coroutine::on_large_stack(|| docgen::document(filename, &source, format)) {
+            Ok(reference) => print!("{}", reference),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
     } else if args.len() >= 2 {
-        // CLI mode
-        let filename = &args[1];
+        // CLI mode; options come before the file name
+        let checking = args[1] == "check";
+        let (mut options, rest) = match Options::parse(&args[if checking { 2 } else { 1 }..]) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                eprintln!("{}", message);
+                process::exit(2);
+            }
+        };
+        let Some(filename) = rest.first() else {
+            eprintln!("Missing file name.");
+            process::exit(2);
+        };
         let source = fs::read_to_string(filename).expect("Failed to read file");
+        options.args = rest[1..].to_vec();
 
-        let result = run_code(&source);
-        println!("{}", result);
+        let (output, status) = if checking {
+            check_code(&source, &options)
+        } else if options.ast {
+            ast_code(&source, &options)
+        } else {
+            run_code(&source, &options)
+        };
+        println!("{}", output);
+        let _ = std::io::stdout().flush();
+        process::exit(status);
     } else {
         eprintln!("Usage:");
-        eprintln!("  ./server <filename>       # CLI mode");
+        eprintln!("  ./server <filename> [args...]        # CLI mode; the program sees `args`");
+        eprintln!("  ./server --deterministic <filename>  # CLI mode, tasks run in a fixed order");
+        eprintln!("  ./server --strict <filename>         # CLI mode, conditions must be booleans");
+        eprintln!("  ./server --ast <filename>            # Print the optimized syntax tree instead of running");
+        eprintln!("  ./server check <filename> # Type-check without running");
+        eprintln!("  ./server test [paths...]  # Run the tests in .aoi files");
+        eprintln!("  ./server lint [--allow|--deny <rule>] <files...>  # Report likely mistakes; `lint --rules` lists them");
+        eprintln!("  ./server doc [--html] <filename>  # Reference from `///` doc comments");
         eprintln!("  ./server server           # Start web server");
     }
 }
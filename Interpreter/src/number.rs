@@ -0,0 +1,362 @@
+use crate::token::TokenType;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+// Numbers are `f64`s. An `f64` holds whole numbers exactly only up to 2^53, so
+// integer arithmetic that goes past that produces a `BigInt` instead. Results
+// that fit again turn back into `f64`s, so big integers only exist while needed.
+//
+// Decimals (`0.1d`) are exact base-10 numbers for money and percentages. They
+// combine with whole numbers, but mixing them with fractional floats is an
+// error, since that would bring the float's rounding back in.
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+// 2^53, the largest whole number every smaller one of which an `f64` holds exactly
+const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+// Caps `**` so a typo like `10 ** 10 ** 10` can't exhaust memory
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+pub(crate) fn is_number(value: &Value) -> bool {
+    value.is::<f64>() || value.is::<i64>() || value.is::<BigInt>() || value.is::<Decimal>()
+}
+
+/// The exact value of a whole number.
+pub(crate) fn as_integer(value: &Value) -> Option<BigInt> {
+    if let Some(n) = value.downcast_ref::<f64>() {
+        is_safe_integer(*n).then(|| BigInt::from(*n as i64))
+    } else if let Some(n) = value.downcast_ref::<i64>() {
+        Some(BigInt::from(*n))
+    } else {
+        value.downcast_ref::<BigInt>().cloned()
+    }
+}
+
+/// Any number as an `f64`; big integers are rounded.
+pub(crate) fn as_float(value: &Value) -> Option<f64> {
+    if let Some(n) = value.downcast_ref::<f64>() {
+        Some(*n)
+    } else if let Some(n) = value.downcast_ref::<i64>() {
+        Some(*n as f64)
+    } else if let Some(n) = value.downcast_ref::<BigInt>() {
+        n.to_f64()
+    } else {
+        value.downcast_ref::<Decimal>().and_then(|n| n.to_f64())
+    }
+}
+
+/// A decimal, or a whole number, as a decimal.
+pub(crate) fn as_decimal(value: &Value) -> Option<Decimal> {
+    if let Some(n) = value.downcast_ref::<Decimal>() {
+        return Some(*n);
+    }
+    as_integer(value).and_then(|n| n.to_i128()).and_then(Decimal::from_i128)
+}
+
+/// Converts any number, or text such as `"12.50"`, to a decimal.
+pub(crate) fn to_decimal(value: &Value) -> Result<Decimal, String> {
+    if let Some(n) = as_decimal(value) {
+        return Ok(n);
+    }
+    if let Some(n) = value.downcast_ref::<f64>() {
+        // Shortest text that reads back as the same float, so 0.1 becomes 0.1 exactly
+        return Decimal::from_str_exact(&n.to_string())
+            .or_else(|_| Decimal::from_scientific(&format!("{:e}", n)))
+            .map_err(|_| format!("Cannot convert {} to a decimal.", n));
+    }
+    if let Some(text) = value.downcast_ref::<String>() {
+        let text = text.trim();
+        return Decimal::from_str_exact(text)
+            .or_else(|_| Decimal::from_scientific(text))
+            .map_err(|_| format!("Cannot convert '{}' to a decimal.", text));
+    }
+    if value.is::<BigInt>() {
+        return Err("Integer is too large for a decimal.".to_string());
+    }
+    Err("decimal() expects a number or a string.".to_string())
+}
+
+/// Rounds to `places` decimal places. Floats are rounded through their
+/// shortest decimal form, so `round(2.675, 2)` gives 2.68.
+pub(crate) fn round(value: &Value, places: u32, mode: &str) -> Result<Value, String> {
+    let strategy = match mode {
+        "half_up" => RoundingStrategy::MidpointAwayFromZero,
+        "half_down" => RoundingStrategy::MidpointTowardZero,
+        "half_even" => RoundingStrategy::MidpointNearestEven,
+        "up" => RoundingStrategy::AwayFromZero,
+        "down" => RoundingStrategy::ToZero,
+        "ceil" => RoundingStrategy::ToPositiveInfinity,
+        "floor" => RoundingStrategy::ToNegativeInfinity,
+        _ => {
+            return Err(format!(
+                "Unknown rounding mode '{}'; use half_up, half_down, half_even, up, down, ceil or floor.",
+                mode
+            ))
+        }
+    };
+
+    if value.is::<BigInt>() {
+        return Ok(value.clone()); // Already whole
+    }
+    let rounded = to_decimal(value)?.round_dp_with_strategy(places, strategy);
+    if value.is::<Decimal>() {
+        Ok(Arc::new(rounded))
+    } else {
+        Ok(Arc::new(rounded.to_f64().unwrap_or(f64::NAN)))
+    }
+}
+
+/// Wraps an exact integer result, as an `f64` whenever that is exact.
+pub(crate) fn integer(n: BigInt) -> Value {
+    match n.to_i64() {
+        Some(small) if small.unsigned_abs() <= MAX_SAFE_INTEGER => Arc::new(small as f64),
+        _ => Arc::new(n),
+    }
+}
+
+fn is_safe_integer(n: f64) -> bool {
+    n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER as f64
+}
+
+/// `+ - * / % **` on two numbers; `None` when an operand is not a number.
+pub(crate) fn arithmetic(operator: &TokenType, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    let (l, r) = (as_float(left)?, as_float(right)?);
+    if left.is::<Decimal>() || right.is::<Decimal>() {
+        return Some(decimal_arithmetic(operator, left, right));
+    }
+
+    // Small whole numbers: exact in an `i128`, without allocating
+    if let (Some(a), Some(b)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
+        if is_safe_integer(*a) && is_safe_integer(*b) {
+            let (a, b) = (*a as i128, *b as i128);
+            let exact = match operator {
+                TokenType::PLUS => Some(a + b),
+                TokenType::MINUS => Some(a - b),
+                TokenType::STAR => Some(a * b),
+                _ => None,
+            };
+            match exact {
+                Some(n) if n.unsigned_abs() <= MAX_SAFE_INTEGER as u128 => return Some(Ok(Arc::new(n as f64))),
+                Some(n) => return Some(Ok(Arc::new(BigInt::from(n)))),
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(a), Some(b)) = (as_integer(left), as_integer(right)) {
+        if let Some(result) = integer_arithmetic(operator, &a, &b) {
+            return Some(result);
+        }
+    }
+    Some(float_arithmetic(operator, l, r))
+}
+
+// `None` when the result is not a whole number, e.g. `7 / 2`
+fn integer_arithmetic(operator: &TokenType, a: &BigInt, b: &BigInt) -> Option<Result<Value, String>> {
+    let result = match operator {
+        TokenType::PLUS => integer(a + b),
+        TokenType::MINUS => integer(a - b),
+        TokenType::STAR => integer(a * b),
+        TokenType::SLASH => {
+            if b.is_zero() {
+                return Some(Err("Division by zero.".to_string()));
+            }
+            let (quotient, remainder) = a.div_rem(b);
+            if !remainder.is_zero() {
+                return None;
+            }
+            integer(quotient)
+        }
+        TokenType::PERCENT => {
+            if b.is_zero() {
+                return Some(Err("Modulo by zero.".to_string()));
+            }
+            integer(a % b) // Takes the sign of the left operand
+        }
+        TokenType::STAR_STAR => {
+            let exponent = b.to_u32()?; // Negative powers are fractions
+            if a.abs() > BigInt::from(1) && a.bits().saturating_mul(exponent as u64) > MAX_POWER_BITS {
+                return Some(Err("Result of '**' is too large.".to_string()));
+            }
+            integer(num_traits::pow(a.clone(), exponent as usize))
+        }
+        _ => return None,
+    };
+    Some(Ok(result))
+}
+
+fn decimal_arithmetic(operator: &TokenType, left: &Value, right: &Value) -> Result<Value, String> {
+    let (Some(a), Some(b)) = (as_decimal(left), as_decimal(right)) else {
+        return Err("Cannot mix a decimal with a fractional float; convert with decimal() or float().".to_string());
+    };
+
+    let result = match operator {
+        TokenType::PLUS => a.checked_add(b),
+        TokenType::MINUS => a.checked_sub(b),
+        TokenType::STAR => a.checked_mul(b),
+        TokenType::SLASH if b.is_zero() => return Err("Division by zero.".to_string()),
+        TokenType::SLASH => a.checked_div(b),
+        TokenType::PERCENT if b.is_zero() => return Err("Modulo by zero.".to_string()),
+        TokenType::PERCENT => a.checked_rem(b),
+        TokenType::STAR_STAR => {
+            let exponent = b
+                .is_integer()
+                .then(|| b.to_i64())
+                .flatten()
+                .ok_or_else(|| "A decimal can only be raised to a whole power.".to_string())?;
+            decimal_power(a, exponent)
+        }
+        _ => return Err("Unknown arithmetic operator.".to_string()),
+    };
+    result
+        .map(|n| Arc::new(n) as Value)
+        .ok_or_else(|| "Decimal overflow.".to_string())
+}
+
+// Exponentiation by squaring; a negative power divides
+fn decimal_power(base: Decimal, exponent: i64) -> Option<Decimal> {
+    let mut result = Decimal::ONE;
+    let mut square = base;
+    let mut rest = exponent.unsigned_abs();
+    while rest > 0 {
+        if rest & 1 == 1 {
+            result = result.checked_mul(square)?;
+        }
+        rest >>= 1;
+        if rest > 0 {
+            square = square.checked_mul(square)?;
+        }
+    }
+    if exponent < 0 {
+        Decimal::ONE.checked_div(result)
+    } else {
+        Some(result)
+    }
+}
+
+fn float_arithmetic(operator: &TokenType, l: f64, r: f64) -> Result<Value, String> {
+    let result = match operator {
+        TokenType::PLUS => l + r,
+        TokenType::MINUS => l - r,
+        TokenType::STAR => l * r,
+        TokenType::SLASH if r == 0.0 => return Err("Division by zero.".to_string()),
+        TokenType::SLASH => l / r,
+        TokenType::PERCENT if r == 0.0 => return Err("Modulo by zero.".to_string()),
+        TokenType::PERCENT => l % r,
+        TokenType::STAR_STAR => l.powf(r),
+        _ => return Err("Unknown arithmetic operator.".to_string()),
+    };
+    Ok(Arc::new(result))
+}
+
+/// Orders two numbers, exactly when both are whole. `None` if either is NaN.
+pub(crate) fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (left.downcast_ref::<f64>(), right.downcast_ref::<f64>()) {
+        return a.partial_cmp(b);
+    }
+    if left.is::<Decimal>() || right.is::<Decimal>() {
+        if let (Some(a), Some(b)) = (as_decimal(left), as_decimal(right)) {
+            return Some(a.cmp(&b));
+        }
+    }
+    if let (Some(a), Some(b)) = (as_integer(left), as_integer(right)) {
+        return Some(a.cmp(&b));
+    }
+    as_float(left)?.partial_cmp(&as_float(right)?)
+}
+
+pub(crate) fn negate(value: &Value) -> Option<Value> {
+    if let Some(n) = value.downcast_ref::<f64>() {
+        Some(Arc::new(-n))
+    } else if let Some(n) = value.downcast_ref::<Decimal>() {
+        Some(Arc::new(-n))
+    } else {
+        value.downcast_ref::<BigInt>().map(|n| integer(-n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(n: f64) -> Value {
+        Arc::new(n)
+    }
+
+    fn text(value: &Value) -> String {
+        match value.downcast_ref::<f64>() {
+            Some(n) => n.to_string(),
+            None => value.downcast_ref::<BigInt>().expect("a number").to_string(),
+        }
+    }
+
+    #[test]
+    fn integers_stay_floats_up_to_two_to_the_53() {
+        let limit = BigInt::from(MAX_SAFE_INTEGER);
+        assert!(integer(limit.clone()).is::<f64>());
+        assert!(integer(-limit.clone()).is::<f64>());
+        assert!(integer(limit.clone() + 1).is::<BigInt>());
+        assert!(integer(-limit - 1).is::<BigInt>());
+    }
+
+    #[test]
+    fn arithmetic_past_two_to_the_53_is_exact() {
+        let limit = float(MAX_SAFE_INTEGER as f64);
+        let sum = arithmetic(&TokenType::PLUS, &limit, &float(1.0)).unwrap().unwrap();
+        assert_eq!(text(&sum), "9007199254740993");
+        let back = arithmetic(&TokenType::MINUS, &sum, &float(1.0)).unwrap().unwrap();
+        assert!(back.is::<f64>(), "results that fit turn back into floats");
+        let product = arithmetic(&TokenType::STAR, &limit, &limit).unwrap().unwrap();
+        assert_eq!(text(&product), "81129638414606681695789005144064");
+    }
+
+    #[test]
+    fn division_is_exact_only_when_whole() {
+        let big: Value = Arc::new(BigInt::from(MAX_SAFE_INTEGER) * 4);
+        let quotient = arithmetic(&TokenType::SLASH, &big, &float(4.0)).unwrap().unwrap();
+        assert_eq!(text(&quotient), "9007199254740992");
+        let half = arithmetic(&TokenType::SLASH, &float(7.0), &float(2.0)).unwrap().unwrap();
+        assert_eq!(text(&half), "3.5");
+        let error = arithmetic(&TokenType::SLASH, &big, &float(0.0)).unwrap().err();
+        assert_eq!(error.as_deref(), Some("Division by zero."));
+    }
+
+    #[test]
+    fn huge_powers_are_refused() {
+        let error = arithmetic(&TokenType::STAR_STAR, &float(10.0), &float(1e9)).unwrap().err();
+        assert_eq!(error.as_deref(), Some("Result of '**' is too large."));
+        let one = arithmetic(&TokenType::STAR_STAR, &float(1.0), &float(1e9)).unwrap().unwrap();
+        assert_eq!(text(&one), "1");
+    }
+
+    #[test]
+    fn big_integers_compare_exactly() {
+        let above: Value = Arc::new(BigInt::from(MAX_SAFE_INTEGER) + 1);
+        let limit = float(MAX_SAFE_INTEGER as f64);
+        assert_eq!(compare(&above, &limit), Some(Ordering::Greater));
+        assert_eq!(compare(&limit, &above), Some(Ordering::Less));
+        assert_eq!(compare(&float(f64::NAN), &limit), None);
+    }
+
+    #[test]
+    fn decimals_do_not_mix_with_fractional_floats() {
+        let tenth: Value = Arc::new(Decimal::new(1, 1));
+        let sum = arithmetic(&TokenType::PLUS, &tenth, &float(2.0)).unwrap().unwrap();
+        assert_eq!(sum.downcast_ref::<Decimal>(), Some(&Decimal::new(21, 1)));
+        assert!(arithmetic(&TokenType::PLUS, &tenth, &float(0.5)).unwrap().is_err());
+    }
+
+    #[test]
+    fn floats_round_through_their_shortest_form() {
+        let rounded = round(&float(2.675), 2, "half_up").unwrap();
+        assert_eq!(rounded.downcast_ref::<f64>(), Some(&2.68));
+        assert!(round(&float(1.0), 0, "sideways").is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::expr::{Binary, Expr, Literal, MatchArm, Unary};
+use crate::interpreter::Interpreter;
+use crate::stmt::Stmt;
+use crate::token::{TokenLiteral, TokenType};
+use rust_decimal::Decimal;
+use std::any::Any;
+use std::sync::Arc;
+
+// Simplifies a checked program before it runs:
+//
+// - operators on literals are computed once: `2 * 3.14` becomes `6.28`
+// - `if` and `while` with a constant condition lose the branch that cannot run
+// - statements after `return` are dropped
+// - parentheses disappear, as the tree already holds the grouping
+//
+// Constants are computed by the interpreter itself, so they behave exactly as
+// at runtime. An expression that fails, like `1 / 0`, is left for the program
+// to report when it gets there.
+pub struct Optimizer {
+    interpreter: Interpreter,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Optimizer {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    // Conditions must be booleans, as with `--strict`
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.interpreter.set_strict(strict);
+        self
+    }
+
+    pub fn optimize(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        self.statements(statements)
+    }
+
+    fn statements(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        let mut optimized = Vec::new();
+        for stmt in statements {
+            let Some(stmt) = self.statement(stmt) else {
+                continue;
+            };
+            let returns = matches!(stmt, Stmt::Return { .. });
+            optimized.push(stmt);
+            if returns {
+                break; // The rest can never run
+            }
+        }
+        optimized
+    }
+
+    // What `stmt` becomes: `None` when it can never run
+    fn statement(&mut self, stmt: Stmt) -> Option<Stmt> {
+        Some(match stmt {
+            Stmt::Expression { expression } => Stmt::Expression {
+                expression: self.expression(expression),
+            },
+            Stmt::Print { expressions, separator, newline, stderr } => Stmt::Print {
+                expressions: self.expressions(expressions),
+                separator: separator.map(|separator| self.expression(separator)),
+                newline,
+                stderr,
+            },
+            Stmt::Var { name, annotation, initializer } => Stmt::Var {
+                name,
+                annotation,
+                initializer: initializer.map(|initializer| self.expression(initializer)),
+            },
+            Stmt::Destructure { pattern, initializer } => Stmt::Destructure {
+                pattern,
+                initializer: self.expression(initializer),
+            },
+            Stmt::Const { name, annotation, initializer } => Stmt::Const {
+                name,
+                annotation,
+                initializer: self.expression(initializer),
+            },
+            Stmt::Block(statements) => Stmt::Block(self.statements(statements)),
+            Stmt::If { keyword, condition, then_branch, else_branch } => {
+                let condition = self.expression(condition);
+                match self.constant_condition(&condition) {
+                    Some(true) => return self.statement(*then_branch),
+                    Some(false) => return else_branch.and_then(|branch| self.statement(*branch)),
+                    None => Stmt::If {
+                        keyword,
+                        condition,
+                        then_branch: Box::new(self.branch(*then_branch)),
+                        else_branch: else_branch.map(|branch| Box::new(self.branch(*branch))),
+                    },
+                }
+            }
+            Stmt::While { keyword, condition, body } => {
+                let condition = self.expression(condition);
+                if self.constant_condition(&condition) == Some(false) {
+                    return None;
+                }
+                Stmt::While {
+                    keyword,
+                    condition,
+                    body: Box::new(self.branch(*body)),
+                }
+            }
+            Stmt::For { initializer, condition, increment, body } => Stmt::For {
+                initializer: initializer.map(|initializer| Box::new(self.branch(*initializer))),
+                condition: condition.map(|condition| self.expression(condition)),
+                increment: increment.map(|increment| self.expression(increment)),
+                body: Box::new(self.branch(*body)),
+            },
+            Stmt::ForIn { index, variable, iterable, body } => Stmt::ForIn {
+                index,
+                variable,
+                iterable: self.expression(iterable),
+                body: Box::new(self.branch(*body)),
+            },
+            Stmt::Function { name, params, param_types, return_type, body, constant, generator, doc } => {
+                Stmt::Function {
+                    name,
+                    params,
+                    param_types,
+                    return_type,
+                    body: self.statements(body),
+                    constant,
+                    generator,
+                    doc,
+                }
+            }
+            Stmt::Return { keyword, value } => Stmt::Return {
+                keyword,
+                value: value.map(|value| self.expression(value)),
+            },
+            Stmt::Yield { keyword, value } => Stmt::Yield {
+                keyword,
+                value: value.map(|value| self.expression(value)),
+            },
+            Stmt::Match { subject, arms } => Stmt::Match {
+                subject: self.expression(subject),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern,
+                        guard: arm.guard.map(|guard| self.expression(guard)),
+                        body: self.branch(arm.body),
+                    })
+                    .collect(),
+            },
+            Stmt::Test { name, body } => Stmt::Test {
+                name,
+                body: self.statements(body),
+            },
+            stmt @ (Stmt::Input { .. } | Stmt::Enum { .. }) => stmt,
+        })
+    }
+
+    // A statement that must stay in place, as the body of a loop or an arm
+    fn branch(&mut self, stmt: Stmt) -> Stmt {
+        self.statement(stmt).unwrap_or(Stmt::Block(Vec::new()))
+    }
+
+    fn expressions(&mut self, expressions: Vec<Expr>) -> Vec<Expr> {
+        expressions.into_iter().map(|expr| self.expression(expr)).collect()
+    }
+
+    fn boxed(&mut self, expr: Expr) -> Box<Expr> {
+        Box::new(self.expression(expr))
+    }
+
+    fn expression(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Grouping(group) => self.expression(*group.expression),
+            Expr::Unary(unary) => {
+                let folded = Expr::Unary(Unary {
+                    operator: unary.operator,
+                    right: self.boxed(*unary.right),
+                });
+                self.fold(folded)
+            }
+            Expr::Binary(binary) => {
+                let folded = Expr::Binary(Binary {
+                    left: self.boxed(*binary.left),
+                    operator: binary.operator,
+                    right: self.boxed(*binary.right),
+                });
+                self.fold(folded)
+            }
+            Expr::Logical { left, operator, right } => {
+                let left = self.expression(*left);
+                let right = self.expression(*right);
+                let Some(value) = self.constant(&left) else {
+                    return Expr::Logical {
+                        left: Box::new(left),
+                        operator,
+                        right: Box::new(right),
+                    };
+                };
+                // The left side alone decides when it short-circuits
+                let decided = match operator.token_type {
+                    TokenType::QUESTION_QUESTION => Ok(!value.is::<()>()),
+                    TokenType::OR => self.interpreter.condition(&value),
+                    _ => self.interpreter.condition(&value).map(|truthy| !truthy),
+                };
+                match decided {
+                    Ok(true) => left,
+                    // `??` always gives the right side; `and`/`or` do unless strict mode checks it
+                    Ok(false) if operator.token_type == TokenType::QUESTION_QUESTION || !self.interpreter.is_strict() => {
+                        right
+                    }
+                    _ => self.fold(Expr::Logical {
+                        left: Box::new(left),
+                        operator,
+                        right: Box::new(right),
+                    }),
+                }
+            }
+            Expr::If { condition, then_branch, else_branch } => {
+                let condition = self.expression(*condition);
+                match self.constant_condition(&condition) {
+                    Some(true) => self.expression(*then_branch),
+                    Some(false) => match else_branch {
+                        Some(branch) => self.expression(*branch),
+                        None => Expr::Literal(Literal::new(TokenLiteral::Null)),
+                    },
+                    None => Expr::If {
+                        condition: Box::new(condition),
+                        then_branch: self.boxed(*then_branch),
+                        else_branch: else_branch.map(|branch| self.boxed(*branch)),
+                    },
+                }
+            }
+            Expr::Assign(name, value) => Expr::Assign(name, self.boxed(*value)),
+            Expr::Call { callee, arguments } => Expr::Call {
+                callee: self.boxed(*callee),
+                arguments: self.expressions(arguments),
+            },
+            Expr::Array { elements } => Expr::Array {
+                elements: self.expressions(elements),
+            },
+            Expr::Index { object, index } => Expr::Index {
+                object: self.boxed(*object),
+                index: self.boxed(*index),
+            },
+            Expr::SafeIndex { object, index } => Expr::SafeIndex {
+                object: self.boxed(*object),
+                index: self.boxed(*index),
+            },
+            Expr::IndexAssign { object, index, value } => Expr::IndexAssign {
+                object: self.boxed(*object),
+                index: self.boxed(*index),
+                value: self.boxed(*value),
+            },
+            Expr::Match { subject, arms } => Expr::Match {
+                subject: self.boxed(*subject),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern,
+                        guard: arm.guard.map(|guard| self.expression(guard)),
+                        body: self.expression(arm.body),
+                    })
+                    .collect(),
+            },
+            Expr::ParallelAssign { targets, values } => Expr::ParallelAssign {
+                targets: self.expressions(targets),
+                values: self.expressions(values),
+            },
+            Expr::Range { start, end, inclusive } => Expr::Range {
+                start: self.boxed(*start),
+                end: self.boxed(*end),
+                inclusive,
+            },
+            Expr::Slice { object, start, end, step } => Expr::Slice {
+                object: self.boxed(*object),
+                start: start.map(|start| self.boxed(*start)),
+                end: end.map(|end| self.boxed(*end)),
+                step: step.map(|step| self.boxed(*step)),
+            },
+            Expr::Get { object, name } => Expr::Get {
+                object: self.boxed(*object),
+                name,
+            },
+            expr @ (Expr::Literal(_) | Expr::Variable(_)) => expr,
+        }
+    }
+
+    // Replaces an operator whose operands are all literals with its result
+    fn fold(&mut self, expr: Expr) -> Expr {
+        let operands: Vec<&Expr> = match &expr {
+            Expr::Unary(unary) => vec![&unary.right],
+            Expr::Binary(binary) => vec![&binary.left, &binary.right],
+            Expr::Logical { left, right, .. } => vec![left, right],
+            _ => return expr,
+        };
+        if !operands.iter().all(|operand| matches!(operand, Expr::Literal(_))) {
+            return expr;
+        }
+        match self.interpreter.evaluate(&expr).ok().and_then(|value| literal(&value)) {
+            Some(literal) => Expr::Literal(Literal::new(literal)),
+            None => expr, // Fails at runtime, or gives a value no literal can hold
+        }
+    }
+
+    fn constant(&mut self, expr: &Expr) -> Option<Arc<dyn Any + Send + Sync>> {
+        match expr {
+            Expr::Literal(_) => self.interpreter.evaluate(expr).ok(),
+            _ => None,
+        }
+    }
+
+    // Whether a condition always holds or never does; `None` when it depends on the run
+    fn constant_condition(&mut self, condition: &Expr) -> Option<bool> {
+        let value = self.constant(condition)?;
+        self.interpreter.condition(&value).ok()
+    }
+}
+
+fn literal(value: &Arc<dyn Any + Send + Sync>) -> Option<TokenLiteral> {
+    if let Some(n) = value.downcast_ref::<f64>() {
+        Some(TokenLiteral::Number(*n))
+    } else if let Some(d) = value.downcast_ref::<Decimal>() {
+        Some(TokenLiteral::Decimal(*d))
+    } else if let Some(s) = value.downcast_ref::<String>() {
+        Some(TokenLiteral::String(s.clone()))
+    } else if let Some(b) = value.downcast_ref::<bool>() {
+        Some(TokenLiteral::Boolean(*b))
+    } else if value.is::<()>() {
+        Some(TokenLiteral::Null)
+    } else {
+        None // Big integers and the like
+    }
+}
@@ -1,7 +1,9 @@
 use crate::expr::Variable;
-use crate::expr::{Binary, Expr, Grouping, Literal, Unary};
-use crate::stmt::Stmt;
+use crate::expr::{Binary, Expr, Grouping, Literal, MatchArm, Pattern, Unary};
+use crate::stmt::{EnumVariant, Stmt};
 use crate::token::{Token, TokenLiteral, TokenType};
+use crate::types::Type;
+use rust_decimal::prelude::ToPrimitive;
 #[allow(dead_code)]
 
 
@@ -9,45 +11,71 @@ use crate::token::{Token, TokenLiteral, TokenType};
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    yields: Vec<bool>, // One entry per function being parsed: whether its body yields
+    errors: Vec<String>,
+    depth: usize,     // Expressions and statements being parsed inside one another
+    too_deep: bool,   // Set once nesting passed MAX_NESTING; parsing then stops
 }
 
+// Deeper nesting is a compile error, so no pass over the tree recurses further
+const MAX_NESTING: usize = 256;
+
 #[derive(Debug)]
 struct ParseError {
+    line: usize,
     message: String,
 }
 
-impl ParseError {
-    fn new(message: &str) -> Self {
-        ParseError {
-            message: message.to_string(),
-        }
-    }
-}
 use std::fmt;
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+        write!(f, "[line {}] {}", self.line, self.message)
     }
 }
 #[allow(dead_code)]
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            yields: Vec::new(),
+            errors: Vec::new(),
+            depth: 0,
+            too_deep: false,
+        }
+    }
+
+    // Parses something that may nest, counting it towards MAX_NESTING
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T, ParseError>) -> Result<T, ParseError> {
+        if self.depth >= MAX_NESTING {
+            let message = format!("Nested more than {} levels deep.", MAX_NESTING);
+            let error = self.error(self.peek(), &message);
+            if !self.too_deep {
+                self.errors.push(error.to_string());
+                self.too_deep = true;
+            }
+            return Err(error);
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
-        self.assignment() // Instead of self.equality()
+        self.nested(Self::assignment) // Instead of self.equality()?
     }
     fn assignment(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.or()?;
+        let expr = self.coalesce()?;
 
         if self.match_tokens(&[TokenType::EQUAL]) {
-            let value = self.assignment()?;
+            let equals = self.previous().clone();
+            let value = self.nested(Self::assignment)?;
 
             // Check for variable assignment
             if let Expr::Variable(var) = expr {
-                return Ok(Expr::Assign(var.name.lexeme.clone(), Box::new(value)));
+                return Ok(Expr::Assign(var.name, Box::new(value)));
             }
 
             // Check for array index assignment
@@ -59,18 +87,18 @@ impl Parser {
                 });
             }
 
-            return Err(ParseError::new("Invalid assignment target."));
+            return Err(self.error(&equals, "Invalid assignment target."));
         }
 
         Ok(expr)
     }
 
-    fn equality(&mut self) -> Expr {
-        let mut expr = self.comparison();
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.comparison()?;
 
         while self.match_tokens(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
             let operator = self.previous().clone();
-            let right = self.comparison();
+            let right = self.comparison()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator,
@@ -78,11 +106,11 @@ impl Parser {
             });
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Expr {
-        let mut expr = self.term();
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.range()?;
 
         while self.match_tokens(&[
             TokenType::GREATER,
@@ -91,7 +119,7 @@ impl Parser {
             TokenType::LESS_EQUAL,
         ]) {
             let operator = self.previous().clone();
-            let right = self.term();
+            let right = self.range()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator,
@@ -99,15 +127,32 @@ impl Parser {
             });
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn term(&mut self) -> Expr {
-        let mut expr = self.factor();
+    fn range(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.bit_or()?;
 
-        while self.match_tokens(&[TokenType::MINUS, TokenType::PLUS]) {
+        if self.match_tokens(&[TokenType::DOT_DOT, TokenType::DOT_DOT_EQUAL]) {
+            let inclusive = self.previous().token_type == TokenType::DOT_DOT_EQUAL;
+            let end = self.bit_or()?;
+            return Ok(Expr::Range {
+                start: Box::new(expr),
+                end: Box::new(end),
+                inclusive,
+            });
+        }
+
+        Ok(expr)
+    }
+
+    // Bitwise operators bind tighter than comparisons, so `n & 1 == 1` needs no parentheses
+    fn bit_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_xor()?;
+
+        while self.match_tokens(&[TokenType::PIPE]) {
             let operator = self.previous().clone();
-            let right = self.factor();
+            let right = self.bit_xor()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator,
@@ -115,15 +160,15 @@ impl Parser {
             });
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn factor(&mut self) -> Expr {
-        let mut expr = self.unary();
+    fn bit_xor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.bit_and()?;
 
-        while self.match_tokens(&[TokenType::SLASH, TokenType::STAR]) {
+        while self.match_tokens(&[TokenType::CARET]) {
             let operator = self.previous().clone();
-            let right = self.unary();
+            let right = self.bit_and()?;
             expr = Expr::Binary(Binary {
                 left: Box::new(expr),
                 operator,
@@ -131,26 +176,101 @@ impl Parser {
             });
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Expr {
-        if self.match_tokens(&[TokenType::BANG, TokenType::MINUS]) {
+    fn bit_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.shift()?;
+
+        while self.match_tokens(&[TokenType::AMPERSAND]) {
             let operator = self.previous().clone();
-            let right = self.unary();
-            return Expr::Unary(Unary {
+            let right = self.shift()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
                 operator,
                 right: Box::new(right),
             });
         }
 
-        match self.primary() {
-            Ok(expr) => expr,
-            Err(_err) => {
-                // Handle the error appropriately, for example by returning a default expression
-                return Expr::Literal(Literal::new(TokenLiteral::Null));
-            }
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
+
+        while self.match_tokens(&[TokenType::LESS_LESS, TokenType::GREATER_GREATER]) {
+            let operator = self.previous().clone();
+            let right = self.term()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
         }
+
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.factor()?;
+
+        while self.match_tokens(&[TokenType::MINUS, TokenType::PLUS]) {
+            let operator = self.previous().clone();
+            let right = self.factor()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+
+        while self.match_tokens(&[TokenType::SLASH, TokenType::STAR, TokenType::PERCENT]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
+            expr = Expr::Binary(Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.match_tokens(&[TokenType::BANG, TokenType::MINUS, TokenType::TILDE]) {
+            let operator = self.previous().clone();
+            let right = self.nested(Self::unary)?;
+            return Ok(Expr::Unary(Unary {
+                operator,
+                right: Box::new(right),
+            }));
+        }
+
+        self.power()
+    }
+
+    // `**` binds tighter than unary minus and groups to the right: `-2 ** 2` is -4
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.primary()?;
+
+        if self.match_tokens(&[TokenType::STAR_STAR]) {
+            let operator = self.previous().clone();
+            let exponent = self.nested(Self::unary)?;
+            return Ok(Expr::Binary(Binary {
+                left: Box::new(base),
+                operator,
+                right: Box::new(exponent),
+            }));
+        }
+
+        Ok(base)
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
@@ -170,6 +290,11 @@ impl Parser {
             return Ok(Expr::Literal(Literal::new(TokenLiteral::Null)));
         }
     
+        //  Handle match expressions
+        if self.match_tokens(&[TokenType::MATCH]) {
+            return self.match_expression();
+        }
+
         //  Handle array literals
         if self.match_tokens(&[TokenType::LEFT_BRACKET]) {
             return self.parse_array();
@@ -187,6 +312,19 @@ impl Parser {
                     expr = self.parse_call(expr)?;
                 } else if self.match_tokens(&[TokenType::LEFT_BRACKET]) {
                     expr = self.parse_index(expr)?;
+                } else if self.match_tokens(&[TokenType::QUESTION_BRACKET]) {
+                    let index = self.expression()?;
+                    self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array index.")?;
+                    expr = Expr::SafeIndex {
+                        object: Box::new(expr),
+                        index: Box::new(index),
+                    };
+                } else if self.match_tokens(&[TokenType::DOT]) {
+                    let name = self.consume(TokenType::IDENTIFIER, "Expect name after '.'.")?;
+                    expr = Expr::Get {
+                        object: Box::new(expr),
+                        name,
+                    };
                 } else {
                     break;
                 }
@@ -198,16 +336,13 @@ impl Parser {
         //  Handle grouping (parentheses)
         if self.match_tokens(&[TokenType::LEFT_PAREN]) {
             let expr = self.expression()?;
-            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.");
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?;
             return Ok(Expr::Grouping(Grouping {
                 expression: Box::new(expr),
             }));
         }
     
-        Err(ParseError::new(&format!(
-            "Error at '{}': Expect expression.",
-            self.peek().lexeme
-        )))
+        Err(self.error(self.peek(), "Expect expression."))
     }
     
 
@@ -220,12 +355,11 @@ impl Parser {
         }
         false
     }
-    fn consume(&mut self, token_type: TokenType, message: &str) -> Token {
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, ParseError> {
         if self.check(token_type) {
-            return self.advance().clone();
+            return Ok(self.advance().clone());
         }
-
-        panic!("{}", message); // Replace with proper error handling later
+        Err(self.error(self.peek(), message))
     }
 
     pub fn check(&self, token_type: TokenType) -> bool {
@@ -242,6 +376,12 @@ impl Parser {
         self.previous()
     }
 
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
     pub fn is_at_end(&self) -> bool {
         self.peek().token_type == TokenType::EOF
     }
@@ -255,7 +395,15 @@ impl Parser {
     }
 
     fn error(&self, token: &Token, message: &str) -> ParseError {
-        ParseError::new(&format!(" Error at '{}': {}", token.lexeme, message))
+        let location = if token.token_type == TokenType::EOF {
+            "end".to_string()
+        } else {
+            format!("'{}'", token.lexeme)
+        };
+        ParseError {
+            line: token.line,
+            message: format!("Error at {}: {}", location, message),
+        }
     }
     fn report(&self, line: usize, location: &str, message: &str) {
         eprintln!("[line {}] Error{}: {}", line, location, message);
@@ -273,11 +421,17 @@ impl Parser {
                 TokenType::CLASS
                 | TokenType::FUN
                 | TokenType::VAR
+                | TokenType::CONST
+                | TokenType::ENUM
                 | TokenType::FOR
                 | TokenType::IF
                 | TokenType::WHILE
+                | TokenType::MATCH
                 | TokenType::PRINT
-                | TokenType::RETURN => return,
+                | TokenType::PRINT_INLINE
+                | TokenType::PRINT_ERROR
+                | TokenType::RETURN
+                | TokenType::YIELD => return,
 
                 _ => {}
             }
@@ -285,114 +439,187 @@ impl Parser {
             self.advance();
         }
     }
-    pub fn parse(&mut self) -> Option<Vec<Stmt>> {
+    /// Parses the whole program, or gives every syntax error found in it.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<String>> {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            if let Some(stmt) = self.statement() {
+            if let Some(stmt) = self.declaration() {
                 statements.push(stmt);
-            } else {
-                return None; // Return None if any statement fails
             }
         }
-        Some(statements) // Return Some if parsing succeeds
+        if !self.errors.is_empty() {
+            return Err(self.errors.clone());
+        }
+        Ok(statements)
     }
 
-    fn statement(&mut self) -> Option<Stmt> {
+    // A statement that fails to parse is reported, and parsing goes on after it
+    fn declaration(&mut self) -> Option<Stmt> {
+        match self.statement() {
+            Ok(stmt) => Some(stmt),
+            Err(_) if self.too_deep => {
+                // Already reported; going on would only add errors about the
+                // missing ends of the enclosing statements
+                self.current = self.tokens.len() - 1;
+                None
+            }
+            Err(error) => {
+                self.errors.push(error.to_string());
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.nested(Self::any_statement)
+    }
+
+    fn any_statement(&mut self) -> Result<Stmt, ParseError> {
         if self.match_tokens(&[TokenType::VAR]) {
-            return Some(self.variable_declaration());
+            if self.check(TokenType::LEFT_BRACKET) {
+                return self.destructuring_declaration();
+            }
+            return self.variable_declaration();
+        }
+        if self.match_tokens(&[TokenType::CONST]) {
+            return self.const_declaration();
+        }
+        // `test` is only a keyword in front of a name, so it stays usable as a variable
+        if self.check(TokenType::IDENTIFIER) && self.peek().lexeme == "test" && self.check_next(TokenType::STRING) {
+            self.advance();
+            let name = self.advance().clone();
+            self.consume(TokenType::LEFT_BRACE, "Expect '{' after test name.")?;
+            return Ok(Stmt::Test { name, body: self.block()? });
         }
         if self.match_tokens(&[TokenType::PRINT]) {
-            return Some(self.print_statement());
+            return self.print_statement(true, false);
+        }
+        if self.match_tokens(&[TokenType::PRINT_INLINE]) {
+            return self.print_statement(false, false);
+        }
+        if self.match_tokens(&[TokenType::PRINT_ERROR]) {
+            return self.print_statement(true, true);
         }
         if self.match_tokens(&[TokenType::LEFT_BRACE]) {
-            return Some(Stmt::Block(self.block())); //  NEW: Handle block statements
+            return Ok(Stmt::Block(self.block()?));
         }
         if self.match_tokens(&[TokenType::IF]) {
-            return Some(self.if_statement());
+            return self.if_statement();
         }
         if self.match_tokens(&[TokenType::SCAN]) {
-            self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'scan'.");
-            let name = self.consume(TokenType::IDENTIFIER, "Expect variable name after 'scan'.");
-            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after variable name.");
-            self.consume(TokenType::SEMICOLON, "Expect ';' after 'scan' statement.");
-            return Some(Stmt::Input { name });
+            self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'scan'.")?;
+            let name = self.consume(TokenType::IDENTIFIER, "Expect variable name after 'scan'.")?;
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after variable name.")?;
+            self.consume(TokenType::SEMICOLON, "Expect ';' after 'scan' statement.")?;
+            return Ok(Stmt::Input { name });
         }
-
         if self.match_tokens(&[TokenType::WHILE]) {
-            return match self.while_statement() {
-                Ok(stmt) => Some(stmt),
-                Err(_err) => {
-                    self.synchronize();
-                    None
-                }
-            };
+            return self.while_statement();
         }
-    if self.match_tokens(&[TokenType::FUN]) {
-        return match self.function() {
-            Ok(stmt) => Some(stmt),
-            Err(_err) => {
-                self.synchronize();
-                None
-            }
-        };
-    }
-    if self.match_tokens(&[TokenType::RETURN]) {
-        return Some(self.return_statement());
-    }
-    if self.match_tokens(&[TokenType::FOR]) {
-            return match self.for_statement() {
-                Ok(stmt) => Some(stmt),
-                Err(_err) => {
-                    self.synchronize();
-                    None
-                }
-            };
-        
-    
+        if self.match_tokens(&[TokenType::FUN]) {
+            return self.function(false);
+        }
+        if self.match_tokens(&[TokenType::RETURN]) {
+            return self.return_statement();
+        }
+        if self.match_tokens(&[TokenType::YIELD]) {
+            return self.yield_statement();
+        }
+        if self.match_tokens(&[TokenType::ENUM]) {
+            return self.enum_declaration();
+        }
+        if self.match_tokens(&[TokenType::MATCH]) {
+            return self.match_statement();
+        }
+        if self.match_tokens(&[TokenType::FOR]) {
+            return self.for_statement();
         }
         self.expression_statement()
     }
 
-    fn print_statement(&mut self) -> Stmt {
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'print'."); // Require '('
+    // `write(a, b, sep: ", ")`; `newline` is false for `write_inline`, `stderr` true for `ewrite`
+    fn print_statement(&mut self, newline: bool, stderr: bool) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'write'.")?; // Require '('
 
-        let value = match self.expression() {
-            Ok(expr) => expr,
-            Err(_) => {
-                self.synchronize();
-                return Stmt::Print {
-                    expression: Expr::Literal(Literal::new(TokenLiteral::Null)),
-                };
+        let mut expressions = Vec::new();
+        let mut separator = None;
+        if !self.check(TokenType::RIGHT_PAREN) {
+            loop {
+                let is_separator = self.check(TokenType::IDENTIFIER)
+                    && self.peek().lexeme == "sep"
+                    && self.check_next(TokenType::COLON);
+                if is_separator {
+                    self.advance();
+                    self.advance();
+                }
+
+                let value = self.expression()?;
+                if is_separator {
+                    separator = Some(value);
+                    break; // The separator comes last
+                }
+                expressions.push(value);
+                if !self.match_tokens(&[TokenType::COMMA]) {
+                    break;
+                }
             }
-        };
+        }
 
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression."); // Require ')'
-        self.consume(TokenType::SEMICOLON, "Expect ';' after print statement."); // Require ';'
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?; // Require ')'
+        self.consume(TokenType::SEMICOLON, "Expect ';' after print statement.")?; // Require ';'
 
-        Stmt::Print { expression: value }
+        Ok(Stmt::Print { expressions, separator, newline, stderr })
     }
 
-    fn expression_statement(&mut self) -> Option<Stmt> {
-        let expr = match self.expression() {
-            Ok(expr) => expr,
-            Err(_err) => {
-                self.synchronize();
-                return None;
-            }
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression()?;
+        let expr = if self.check(TokenType::COMMA) {
+            self.parallel_assignment(expr)?
+        } else {
+            expr
         };
-        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.");
-        Some(Stmt::Expression { expression: expr })
+        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression { expression: expr })
+    }
+
+    // `a, b = b, a;` or `a, b = pair;`. Parsed at statement level because
+    // commas already separate call arguments and array elements.
+    fn parallel_assignment(&mut self, first: Expr) -> Result<Expr, ParseError> {
+        let mut targets = vec![first];
+        while self.match_tokens(&[TokenType::COMMA]) {
+            targets.push(self.or()?);
+        }
+        for target in &targets {
+            if !matches!(target, Expr::Variable(_) | Expr::Index { .. }) {
+                return Err(self.error(self.peek(), "Invalid assignment target."));
+            }
+        }
+
+        self.consume(TokenType::EQUAL, "Expect '=' after assignment targets.")?;
+        let mut values = vec![self.expression()?];
+        while self.match_tokens(&[TokenType::COMMA]) {
+            values.push(self.expression()?);
+        }
+
+        if values.len() > 1 && values.len() != targets.len() {
+            let message = format!(
+                "Cannot assign {} values to {} targets.",
+                values.len(),
+                targets.len()
+            );
+            return Err(self.error(self.previous(), &message));
+        }
+
+        Ok(Expr::ParallelAssign { targets, values })
     }
 
-    fn variable_declaration(&mut self) -> Stmt {
-        let name_token = self.consume(TokenType::IDENTIFIER, "Expect variable name.");
-        let name = name_token.lexeme.clone();
+    fn variable_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
+        let annotation = self.optional_annotation()?;
 
         let initializer = if self.match_tokens(&[TokenType::EQUAL]) {
-            match self.expression() {
-                Ok(expr) => Some(expr),
-                Err(_) => None,
-            }
+            Some(self.expression()?)
         } else {
             None
         };
@@ -400,48 +627,114 @@ impl Parser {
         self.consume(
             TokenType::SEMICOLON,
             "Expect ';' after variable declaration.",
-        );
+        )?;
+
+        Ok(Stmt::Var {
+            name,
+            annotation,
+            initializer,
+        })
+    }
+
+    // `: type` after a name, if present
+    fn optional_annotation(&mut self) -> Result<Option<Type>, ParseError> {
+        if self.match_tokens(&[TokenType::COLON]) {
+            Ok(Some(self.type_annotation()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // `num`, `str`, `bool`, `nil`, `any`, `range`, `fun`, `[T]` or an enum name
+    fn type_annotation(&mut self) -> Result<Type, ParseError> {
+        if self.match_tokens(&[TokenType::LEFT_BRACKET]) {
+            let element = self.type_annotation()?;
+            self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array element type.")?;
+            return Ok(Type::Array(Box::new(element)));
+        }
+        if self.match_tokens(&[TokenType::NIL]) {
+            return Ok(Type::Nil);
+        }
+        if self.match_tokens(&[TokenType::FUN]) {
+            return Ok(Type::Function(None));
+        }
+        if self.match_tokens(&[TokenType::IDENTIFIER]) {
+            return Ok(match self.previous().lexeme.as_str() {
+                "num" => Type::Num,
+                "str" => Type::Str,
+                "bool" => Type::Bool,
+                "any" => Type::Any,
+                "range" => Type::Range,
+                name => Type::Named(name.to_string()),
+            });
+        }
+        Err(self.error(self.peek(), "Expect type."))
+    }
+
+    // `var [a, b] = pair;` and `var [head, ...tail] = arr;`
+    fn destructuring_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let pattern = self.pattern()?;
+        if !Self::is_binding_pattern(&pattern) {
+            return Err(self.error(self.previous(), "Only names can be declared by destructuring."));
+        }
+
+        self.consume(TokenType::EQUAL, "Expect '=' after destructuring pattern.")?;
+        let initializer = self.expression()?;
+        self.consume(
+            TokenType::SEMICOLON,
+            "Expect ';' after variable declaration.",
+        )?;
 
-        Stmt::Var { name, initializer }
+        Ok(Stmt::Destructure { pattern, initializer })
+    }
+
+    fn is_binding_pattern(pattern: &Pattern) -> bool {
+        match pattern {
+            Pattern::Binding(_) | Pattern::Wildcard => true,
+            Pattern::Array { elements, .. } => elements.iter().all(Self::is_binding_pattern),
+            Pattern::Literal(_) | Pattern::Range { .. } | Pattern::Variant { .. } => false,
+        }
     }
 
-    fn block(&mut self) -> Vec<Stmt> {
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
         let mut statements = Vec::new();
 
         while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
-            if let Some(stmt) = self.statement() {
+            if let Some(stmt) = self.declaration() {
                 statements.push(stmt);
             }
         }
 
-        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.");
-        statements
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(statements)
     }
 
-    fn if_statement(&mut self) -> Stmt {
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.");
-        let condition = self.expression().unwrap();
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.");
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.")?;
 
-        let then_branch = Box::new(self.statement().unwrap());
+        let then_branch = Box::new(self.statement()?);
         let else_branch = if self.match_tokens(&[TokenType::ELSE]) {
-            Some(Box::new(self.statement().unwrap()))
+            Some(Box::new(self.statement()?))
         } else {
             None
         };
 
-        Stmt::If {
+        Ok(Stmt::If {
+            keyword,
             condition,
             then_branch,
             else_branch,
-        }
+        })
     }
     fn and(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.equality(); // Parse left-hand side
+        let mut expr = self.equality()?; // Parse left-hand side
 
         while self.match_tokens(&[TokenType::AND]) {
             let operator = self.previous().clone();
-            let right = self.equality(); // Parse right-hand side
+            let right = self.equality()?; // Parse right-hand side
             expr = Expr::Logical {
                 left: Box::new(expr),
                 operator,
@@ -466,28 +759,66 @@ impl Parser {
 
         Ok(expr)
     }
+    // `a ?? b` binds looser than `or`, so `x ?? a or b` is `x ?? (a or b)`
+    fn coalesce(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.or()?;
+
+        while self.match_tokens(&[TokenType::QUESTION_QUESTION]) {
+            let operator = self.previous().clone();
+            let right = self.or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
     fn while_statement(&mut self) -> Result<Stmt, ParseError> {
-        self.consume(TokenType::LEFT_PAREN, "Expected '(' after 'while'.");
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LEFT_PAREN, "Expected '(' after 'while'.")?;
         let condition = self.expression()?; // Parse condition
-        self.consume(TokenType::RIGHT_PAREN, "Expected ')' after condition.");
-        let body = match self.statement() {
-            Some(stmt) => stmt,
-            None => return Err(ParseError::new("Expected statement for while body")),
-        }; // Parse loop body
+        self.consume(TokenType::RIGHT_PAREN, "Expected ')' after condition.")?;
+        let body = self.statement()?; // Parse loop body
 
         Ok(Stmt::While {
+            keyword,
             condition,
             body: Box::new(body),
         })
     }
     fn for_statement(&mut self) -> Result<Stmt, ParseError> {
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.");
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.")?;
+
+        // 🔹 `for (item in collection)` and `for (i, item in collection)`
+        if self.check(TokenType::IDENTIFIER)
+            && (self.check_next(TokenType::IN) || self.check_next(TokenType::COMMA))
+        {
+            let mut index = None;
+            let mut variable = self.advance().clone();
+            if self.match_tokens(&[TokenType::COMMA]) {
+                index = Some(variable);
+                variable = self.consume(TokenType::IDENTIFIER, "Expect item name after ','.")?;
+            }
+            self.consume(TokenType::IN, "Expect 'in' after loop variable.")?;
+            let iterable = self.expression()?;
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for-in clause.")?;
+            let body = self.statement()?;
+            return Ok(Stmt::ForIn {
+                index,
+                variable,
+                iterable,
+                body: Box::new(body),
+            });
+        }
 
         // 🔹 Parse the initializer (`var i = 0;`)
         let initializer = if self.match_tokens(&[TokenType::SEMICOLON]) {
             None
         } else if self.match_tokens(&[TokenType::VAR]) {
-            Some(Box::new(self.variable_declaration()))
+            Some(Box::new(self.variable_declaration()?))
         } else {
             Some(Box::new(Stmt::Expression {
                 expression: self.expression()?,
@@ -500,7 +831,7 @@ impl Parser {
         } else {
             None
         };
-        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.");
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")?;
 
         // 🔹 Parse the increment (`i = i + 1`)
         let increment = if !self.check(TokenType::RIGHT_PAREN) {
@@ -508,13 +839,10 @@ impl Parser {
         } else {
             None
         };
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.");
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.")?;
 
         // 🔹 Parse the loop body (`{ write(i); }`)
-        let mut body = match self.statement() {
-            Some(stmt) => stmt,
-            None => return Err(ParseError::new("Expected statement for loop body")),
-        };
+        let mut body = self.statement()?;
 
         //  Append the increment to the end of the loop
         if let Some(inc) = increment {
@@ -523,6 +851,7 @@ impl Parser {
 
         //  Convert into `while (condition) { body }`
         let while_loop = Stmt::While {
+            keyword,
             condition: condition
                 .unwrap_or(Expr::Literal(Literal::new(TokenLiteral::Boolean(true)))), // Default: Always true
             body: Box::new(body),
@@ -535,28 +864,99 @@ impl Parser {
 
         Ok(while_loop)
     }
-    fn function(&mut self) -> Result<Stmt, ParseError> {
-        let name = self.consume(TokenType::IDENTIFIER, "Expect function name.").clone();
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after function name.");
+    fn const_declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.match_tokens(&[TokenType::FUN]) {
+            return self.function(true);
+        }
+
+        let name = self.consume(TokenType::IDENTIFIER, "Expect constant name.")?;
+        let annotation = self.optional_annotation()?;
+        self.consume(TokenType::EQUAL, "Expect '=' after constant name.")?;
+        let initializer = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after constant declaration.")?;
+        Ok(Stmt::Const {
+            name,
+            annotation,
+            initializer,
+        })
+    }
+
+    fn function(&mut self, constant: bool) -> Result<Stmt, ParseError> {
+        // Doc comments attach to the declaration's first token, `const` or `fun`
+        let keyword = if constant { &self.tokens[self.current - 2] } else { self.previous() };
+        let doc = keyword.doc.clone();
+        let name = self.consume(TokenType::IDENTIFIER, "Expect function name.")?;
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after function name.")?;
     
         let mut params = Vec::new();
+        let mut param_types = Vec::new();
         if !self.check(TokenType::RIGHT_PAREN) {
             loop {
-                params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name.").clone());
+                params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?);
+                param_types.push(self.optional_annotation()?);
                 if !self.match_single(&TokenType::COMMA) {
                     break;
                 }
             }
         }
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.");
-        self.consume(TokenType::LEFT_BRACE, "Expect '{' before function body.");
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.")?;
+        let return_type = self.optional_annotation()?;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before function body.")?;
     
-        let body = self.block(); // Assume `block()` parses a block of statements
-        Ok(Stmt::Function { name, params, body })
+        self.yields.push(false);
+        let body = self.block();
+        let generator = self.yields.pop().unwrap_or(false);
+        let body = body?;
+        Ok(Stmt::Function {
+            name,
+            params,
+            param_types,
+            return_type,
+            body,
+            constant,
+            generator,
+            doc,
+        })
     }
     
+    // `enum Shape { Circle(r), Rect(w, h), Empty }`
+    fn enum_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expect enum name.")?;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' after enum name.")?;
+
+        let mut variants: Vec<EnumVariant> = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let variant = self.consume(TokenType::IDENTIFIER, "Expect variant name.")?;
+            if variants.iter().any(|v| v.name.lexeme == variant.lexeme) {
+                let message = format!("Duplicate variant '{}' in enum '{}'.", variant.lexeme, name.lexeme);
+                return Err(self.error(&variant, &message));
+            }
+
+            let mut fields = Vec::new();
+            if self.match_tokens(&[TokenType::LEFT_PAREN]) {
+                if !self.check(TokenType::RIGHT_PAREN) {
+                    loop {
+                        fields.push(self.consume(TokenType::IDENTIFIER, "Expect field name.")?);
+                        if !self.match_tokens(&[TokenType::COMMA]) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RIGHT_PAREN, "Expect ')' after variant fields.")?;
+            }
+            variants.push(EnumVariant { name: variant, fields });
+
+            if !self.match_tokens(&[TokenType::COMMA]) {
+                break; // A trailing comma is allowed
+            }
+        }
+
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after enum variants.")?;
+        Ok(Stmt::Enum { name, variants })
+    }
+
     fn match_single(&mut self, token_type: &TokenType) -> bool {
-        if self.check(token_type.clone()) {
+        if self.check(*token_type) {
             self.advance();
             true
         } else {
@@ -580,7 +980,7 @@ impl Parser {
             }
         }
     
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.");
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.")?;
         
         Ok(Expr::Call {
             callee: Box::new(callee),
@@ -588,15 +988,32 @@ impl Parser {
         })
     }
 
-    fn return_statement(&mut self) -> Stmt {
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
         let keyword = self.previous().clone();
         let value = if !self.check(TokenType::SEMICOLON) {
-            Some(self.expression().unwrap())
+            Some(self.expression()?)
         } else {
             None
         };
-        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.");
-        Stmt::Return { keyword, value }
+        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    // `yield value;` makes the enclosing function a generator
+    fn yield_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        match self.yields.last_mut() {
+            Some(yields) => *yields = true,
+            None => return Err(self.error(&keyword, "Can't yield outside a function.")),
+        }
+
+        let value = if !self.check(TokenType::SEMICOLON) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after yield value.")?;
+        Ok(Stmt::Yield { keyword, value })
     }
 
     fn parse_array(&mut self) -> Result<Expr, ParseError> {
@@ -611,17 +1028,195 @@ impl Parser {
             }
         }
 
-        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array elements.");
+        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array elements.")?;
         Ok(Expr::Array { elements })
     }
 
     fn parse_index(&mut self, object: Expr) -> Result<Expr, ParseError> {
-        let index = self.expression()?;
-        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array index.");
+        let start = if self.check(TokenType::COLON) {
+            None
+        } else {
+            Some(Box::new(self.expression()?))
+        };
+
+        //  `arr[start:end:step]`, where every part is optional
+        if self.match_tokens(&[TokenType::COLON]) {
+            let end = if self.check(TokenType::COLON) || self.check(TokenType::RIGHT_BRACKET) {
+                None
+            } else {
+                Some(Box::new(self.expression()?))
+            };
+            let step = if self.match_tokens(&[TokenType::COLON]) && !self.check(TokenType::RIGHT_BRACKET) {
+                Some(Box::new(self.expression()?))
+            } else {
+                None
+            };
+            self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after slice.")?;
+            return Ok(Expr::Slice {
+                object: Box::new(object),
+                start,
+                end,
+                step,
+            });
+        }
+
+        let Some(index) = start else {
+            return Err(self.error(self.peek(), "Expect array index."));
+        };
+        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array index.")?;
         Ok(Expr::Index {
             object: Box::new(object),
-            index: Box::new(index),
+            index,
         })
     }
     
+
+    fn match_subject(&mut self) -> Result<Expr, ParseError> {
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'match'.")?;
+        let subject = self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after match value.")?;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before match arms.")?;
+        Ok(subject)
+    }
+
+    // Parses `pattern [if guard] =>`, leaving the parser at the arm body
+    fn match_arm_head(&mut self) -> Result<(Pattern, Option<Expr>), ParseError> {
+        let pattern = self.pattern()?;
+        let guard = if self.match_tokens(&[TokenType::IF]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::FAT_ARROW, "Expect '=>' after match pattern.")?;
+        Ok((pattern, guard))
+    }
+
+    fn match_statement(&mut self) -> Result<Stmt, ParseError> {
+        let subject = self.match_subject()?;
+
+        let mut arms = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let (pattern, guard) = self.match_arm_head()?;
+            let body = self.statement()?;
+            self.match_tokens(&[TokenType::COMMA]); // Optional separator between arms
+            arms.push(MatchArm { pattern, guard, body });
+        }
+
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after match arms.")?;
+        Ok(Stmt::Match { subject, arms })
+    }
+
+    fn match_expression(&mut self) -> Result<Expr, ParseError> {
+        let subject = self.match_subject()?;
+
+        let mut arms = Vec::new();
+        while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
+            let (pattern, guard) = self.match_arm_head()?;
+            let body = self.expression()?;
+            arms.push(MatchArm { pattern, guard, body });
+            if !self.match_tokens(&[TokenType::COMMA]) {
+                break;
+            }
+        }
+
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after match arms.")?;
+        Ok(Expr::Match {
+            subject: Box::new(subject),
+            arms,
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        if self.match_tokens(&[TokenType::IDENTIFIER]) {
+            let name = self.previous().clone();
+            if self.match_tokens(&[TokenType::DOT]) {
+                return self.variant_pattern(name);
+            }
+            if name.lexeme == "_" {
+                return Ok(Pattern::Wildcard);
+            }
+            return Ok(Pattern::Binding(name));
+        }
+
+        if self.match_tokens(&[TokenType::LEFT_BRACKET]) {
+            let mut elements = Vec::new();
+            let mut rest = None;
+            if !self.check(TokenType::RIGHT_BRACKET) {
+                loop {
+                    if self.match_tokens(&[TokenType::DOT_DOT_DOT]) {
+                        rest = Some(self.consume(TokenType::IDENTIFIER, "Expect name after '...'.")?);
+                        break; // The rest pattern must come last
+                    }
+                    elements.push(self.pattern()?);
+                    if !self.match_tokens(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after array pattern.")?;
+            return Ok(Pattern::Array { elements, rest });
+        }
+
+        if self.match_tokens(&[TokenType::TRUE]) {
+            return Ok(Pattern::Literal(TokenLiteral::Boolean(true)));
+        }
+        if self.match_tokens(&[TokenType::FALSE]) {
+            return Ok(Pattern::Literal(TokenLiteral::Boolean(false)));
+        }
+        if self.match_tokens(&[TokenType::NIL]) {
+            return Ok(Pattern::Literal(TokenLiteral::Null));
+        }
+        if self.match_tokens(&[TokenType::STRING]) {
+            let value = self.previous().literal.clone().unwrap_or(TokenLiteral::Null);
+            return Ok(Pattern::Literal(value));
+        }
+
+        if self.check(TokenType::NUMBER) || self.check(TokenType::MINUS) {
+            let start = self.pattern_number()?;
+            if self.match_tokens(&[TokenType::DOT_DOT, TokenType::DOT_DOT_EQUAL]) {
+                let inclusive = self.previous().token_type == TokenType::DOT_DOT_EQUAL;
+                let end = self.pattern_number()?;
+                return Ok(Pattern::Range { start, end, inclusive });
+            }
+            return Ok(Pattern::Literal(TokenLiteral::Number(start)));
+        }
+
+        Err(self.error(self.peek(), "Expect pattern."))
+    }
+
+    // `Shape.Circle(r)` or `Shape.Empty`, after the enum name and the dot
+    fn variant_pattern(&mut self, enum_name: Token) -> Result<Pattern, ParseError> {
+        let variant = self.consume(TokenType::IDENTIFIER, "Expect variant name after '.'.")?;
+        let mut fields = Vec::new();
+        if self.match_tokens(&[TokenType::LEFT_PAREN]) {
+            if !self.check(TokenType::RIGHT_PAREN) {
+                loop {
+                    fields.push(self.pattern()?);
+                    if !self.match_tokens(&[TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after variant pattern.")?;
+        }
+        Ok(Pattern::Variant {
+            enum_name,
+            variant,
+            fields,
+        })
+    }
+
+    fn pattern_number(&mut self) -> Result<f64, ParseError> {
+        let negative = self.match_tokens(&[TokenType::MINUS]);
+        if self.match_tokens(&[TokenType::NUMBER]) {
+            if let Some(TokenLiteral::Number(n)) = self.previous().literal {
+                return Ok(if negative { -n } else { n });
+            }
+            if let Some(TokenLiteral::Decimal(d)) = self.previous().literal {
+                let n = d.to_f64().unwrap_or(f64::NAN);
+                return Ok(if negative { -n } else { n });
+            }
+        }
+        Err(self.error(self.peek(), "Expect number in pattern."))
+    }
 }
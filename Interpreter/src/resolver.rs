@@ -0,0 +1,301 @@
+use crate::expr::{Expr, Pattern};
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::collections::HashMap;
+
+// Static pass run after parsing. It mirrors the interpreter's scoping rules
+// and rejects writes to constants that are visible at the assignment site.
+pub struct Resolver {
+    scopes: Vec<HashMap<String, Option<usize>>>, // Name -> Some(declaration line) for constants
+    errors: Vec<String>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: vec![HashMap::new()], // Global scope
+            errors: Vec::new(),
+        }
+    }
+
+    /// Checks a whole program, returning one message per problem found.
+    pub fn resolve(mut self, statements: &[Stmt]) -> Vec<String> {
+        for stmt in statements {
+            self.resolve_stmt(stmt);
+        }
+        self.errors
+    }
+
+    fn error(&mut self, line: usize, message: String) {
+        self.errors.push(format!("[line {}] Error: {}", line, message));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, constant: bool) {
+        let scope = self.scopes.last_mut().expect("resolver always has a scope");
+        if let Some(Some(line)) = scope.get(&name.lexeme) {
+            let message = format!(
+                "Cannot redeclare constant '{}' (declared on line {}, redeclared on line {}).",
+                name.lexeme, line, name.line
+            );
+            self.error(name.line, message);
+            return;
+        }
+        let entry = if constant { Some(name.line) } else { None };
+        scope.insert(name.lexeme.clone(), entry);
+    }
+
+    fn check_assign(&mut self, name: &Token) {
+        for scope in self.scopes.iter().rev() {
+            if let Some(entry) = scope.get(&name.lexeme) {
+                if let Some(line) = entry {
+                    let message = format!(
+                        "Cannot assign to constant '{}' (declared on line {}, assigned on line {}).",
+                        name.lexeme, line, name.line
+                    );
+                    self.error(name.line, message);
+                }
+                return; // Innermost binding wins
+            }
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression } => self.resolve_expr(expression),
+            Stmt::Print { expressions, separator, .. } => {
+                for expression in expressions.iter().chain(separator) {
+                    self.resolve_expr(expression);
+                }
+            }
+            Stmt::Var { name, initializer, .. } => {
+                if let Some(init) = initializer {
+                    self.resolve_expr(init);
+                }
+                self.declare(name, false);
+            }
+            Stmt::Destructure { pattern, initializer } => {
+                self.resolve_expr(initializer);
+                self.declare_pattern(pattern);
+            }
+            Stmt::Const { name, initializer, .. } => {
+                self.resolve_expr(initializer);
+                self.declare(name, true);
+            }
+            Stmt::Block(statements) | Stmt::Test { body: statements, .. } => {
+                self.begin_scope();
+                for stmt in statements {
+                    self.resolve_stmt(stmt);
+                }
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::While { condition, body, .. } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            }
+            Stmt::Input { name } => self.check_assign(name),
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.begin_scope();
+                if let Some(init) = initializer {
+                    self.resolve_stmt(init);
+                }
+                if let Some(cond) = condition {
+                    self.resolve_expr(cond);
+                }
+                if let Some(inc) = increment {
+                    self.resolve_expr(inc);
+                }
+                self.resolve_stmt(body);
+                self.end_scope();
+            }
+            Stmt::ForIn {
+                index,
+                variable,
+                iterable,
+                body,
+            } => {
+                self.resolve_expr(iterable);
+                self.begin_scope();
+                if let Some(index) = index {
+                    self.declare(index, false);
+                }
+                self.declare(variable, false);
+                self.resolve_stmt(body);
+                self.end_scope();
+            }
+            Stmt::Function {
+                name,
+                params,
+                body,
+                constant,
+                ..
+            } => {
+                self.declare(name, *constant);
+                self.begin_scope();
+                for param in params {
+                    self.declare(param, false);
+                }
+                for stmt in body {
+                    self.resolve_stmt(stmt);
+                }
+                self.end_scope();
+            }
+            Stmt::Return { value, .. } | Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Enum { name, .. } => self.declare(name, false),
+            Stmt::Match { subject, arms } => {
+                self.resolve_expr(subject);
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_stmt(&arm.body);
+                    self.end_scope();
+                }
+            }
+        }
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => self.declare(name, false),
+            Pattern::Array { elements, rest } => {
+                for element in elements {
+                    self.declare_pattern(element);
+                }
+                if let Some(rest) = rest {
+                    self.declare(rest, false);
+                }
+            }
+            Pattern::Variant { fields, .. } => {
+                for field in fields {
+                    self.declare_pattern(field);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(name, value) => {
+                self.resolve_expr(value);
+                self.check_assign(name);
+            }
+            Expr::Binary(binary) => {
+                self.resolve_expr(&binary.left);
+                self.resolve_expr(&binary.right);
+            }
+            Expr::Grouping(group) => self.resolve_expr(&group.expression),
+            Expr::Unary(unary) => self.resolve_expr(&unary.right),
+            Expr::Literal(_) | Expr::Variable(_) => {}
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_expr(else_branch);
+                }
+            }
+            Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Call { callee, arguments } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Array { elements } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Index { object, index } | Expr::SafeIndex { object, index } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::IndexAssign {
+                object,
+                index,
+                value,
+            } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            }
+            Expr::ParallelAssign { targets, values } => {
+                for value in values {
+                    self.resolve_expr(value);
+                }
+                for target in targets {
+                    match target {
+                        Expr::Variable(variable) => self.check_assign(&variable.name),
+                        _ => self.resolve_expr(target),
+                    }
+                }
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Range { start, end, .. } => {
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+            }
+            Expr::Slice {
+                object,
+                start,
+                end,
+                step,
+            } => {
+                self.resolve_expr(object);
+                for part in [start, end, step].into_iter().flatten() {
+                    self.resolve_expr(part);
+                }
+            }
+            Expr::Match { subject, arms } => {
+                self.resolve_expr(subject);
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_expr(&arm.body);
+                    self.end_scope();
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
-use crate::expr::Expr;
+use crate::expr::{Expr, MatchArm, Pattern};
 use crate::token::Token;
+use crate::types::Type;
 #[allow(dead_code)]
 #[derive(Clone)]
 pub enum Stmt {
@@ -7,19 +8,34 @@ pub enum Stmt {
         expression: Expr,
     },
     Print {
-        expression: Expr,
+        expressions: Vec<Expr>,
+        separator: Option<Expr>, // `sep: ", "`, a space by default
+        newline: bool,           // false for `write_inline`
+        stderr: bool,            // true for `ewrite`
     },
     Var {
-        name: String,
+        name: Token,
+        annotation: Option<Type>, // `var x: num`
         initializer: Option<Expr>,
     },
+    Destructure {
+        pattern: Pattern, // Array pattern made only of names, `_` and `...rest`
+        initializer: Expr,
+    },
+    Const {
+        name: Token,
+        annotation: Option<Type>,
+        initializer: Expr,
+    },
     Block(Vec<Stmt>),
     If {
+        keyword: Token,
         condition: Expr,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
     },
     While {
+        keyword: Token, // `while`, or `for` for loops built from one
         condition: Expr,
         body: Box<Stmt>,
     },
@@ -32,13 +48,47 @@ pub enum Stmt {
         increment: Option<Expr>,
         body: Box<Stmt>,
     },
+    ForIn {
+        index: Option<Token>, // `i` in `for (i, item in ...)`
+        variable: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
     Function {
         name: Token,
         params: Vec<Token>,
+        param_types: Vec<Option<Type>>, // `a: num`, one entry per parameter
+        return_type: Option<Type>,      // `fun f(): num`
         body: Vec<Stmt>,
+        constant: bool,  // Declared with `const fun`
+        generator: bool, // Body contains `yield`
+        doc: Option<String>, // From `///` comments before the declaration
     },
     Return {
         keyword: Token,
         value: Option<Expr>,
     },
+    Yield {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Match {
+        subject: Expr,
+        arms: Vec<MatchArm<Stmt>>,
+    },
+    Enum {
+        name: Token,
+        variants: Vec<EnumVariant>,
+    },
+    Test {
+        name: Token, // The string naming the test
+        body: Vec<Stmt>,
+    },
+}
+
+// One `Circle(r)` entry of an `enum` declaration
+#[derive(Clone)]
+pub struct EnumVariant {
+    pub name: Token,
+    pub fields: Vec<Token>,
 }
@@ -0,0 +1,169 @@
+use crate::coroutine::Coroutine;
+use rand::seq::SliceRandom;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Green tasks and channels. Tasks are coroutines that run one at a time; a
+// task gives control back to the scheduler whenever it has to wait.
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+// Most tasks a run can have unfinished at once. A task holds a thread from
+// its first turn until it finishes, so this bounds the threads of a run.
+pub(crate) const MAX_TASKS: usize = 1000;
+
+// Created by `spawn(fn, args...)`
+pub(crate) struct Task {
+    pub(crate) name: String,
+    pub(crate) coroutine: Arc<Mutex<Coroutine>>,
+    result: Mutex<Option<Result<Value, String>>>, // Set once the task has finished
+    joined: AtomicBool, // A `join` got the result, and reports a failure itself
+}
+
+impl Task {
+    pub(crate) fn new(name: String, coroutine: Arc<Mutex<Coroutine>>) -> Self {
+        Task {
+            name,
+            coroutine,
+            result: Mutex::new(None),
+            joined: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn result(&self) -> Option<Result<Value, String>> {
+        self.result.lock().expect("task poisoned").clone()
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.result.lock().expect("task poisoned").is_some()
+    }
+
+    pub(crate) fn set_joined(&self) {
+        self.joined.store(true, Ordering::SeqCst);
+    }
+
+    fn finish(&self, result: Result<Value, String>) {
+        *self.result.lock().expect("task poisoned") = Some(result);
+    }
+}
+
+// What happened when a channel operation was attempted without waiting
+pub(crate) enum Attempt<T> {
+    Done(T),
+    WouldBlock,
+}
+
+struct ChannelState {
+    queue: VecDeque<Value>,
+    closed: bool,
+}
+
+// Created by `channel()` (unbounded) or `channel(capacity)`
+pub(crate) struct Channel {
+    capacity: Option<usize>,
+    state: Mutex<ChannelState>,
+}
+
+impl Channel {
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Channel {
+            capacity,
+            state: Mutex::new(ChannelState {
+                queue: VecDeque::new(),
+                closed: false,
+            }),
+        }
+    }
+
+    /// Queues `value` unless the channel is full. Sending on a closed channel is an error.
+    pub(crate) fn try_send(&self, value: Value) -> Result<Attempt<()>, String> {
+        let mut state = self.state.lock().expect("channel poisoned");
+        if state.closed {
+            return Err("Cannot send on a closed channel.".to_string());
+        }
+        if self.capacity.is_some_and(|capacity| state.queue.len() >= capacity) {
+            return Ok(Attempt::WouldBlock);
+        }
+        state.queue.push_back(value);
+        Ok(Attempt::Done(()))
+    }
+
+    /// Takes the oldest value. `Done(None)` means the channel is closed and drained.
+    pub(crate) fn try_recv(&self) -> Attempt<Option<Value>> {
+        let mut state = self.state.lock().expect("channel poisoned");
+        match state.queue.pop_front() {
+            Some(value) => Attempt::Done(Some(value)),
+            None if state.closed => Attempt::Done(None),
+            None => Attempt::WouldBlock,
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        self.state.lock().expect("channel poisoned").closed = true;
+    }
+}
+
+// Shared by the main program and every task of one run
+pub(crate) struct Scheduler {
+    tasks: Mutex<Vec<Arc<Task>>>,  // Unfinished ones, in spawn order
+    failed: Mutex<Vec<Arc<Task>>>, // Finished with an error
+    progress: AtomicUsize, // Bumped whenever something a waiter may be waiting for happens
+    deterministic: bool,   // Run tasks in spawn order instead of a random order
+}
+
+impl Scheduler {
+    pub(crate) fn new(deterministic: bool) -> Self {
+        Scheduler {
+            tasks: Mutex::new(Vec::new()),
+            failed: Mutex::new(Vec::new()),
+            progress: AtomicUsize::new(0),
+            deterministic,
+        }
+    }
+
+    pub(crate) fn add(&self, task: Arc<Task>) {
+        self.tasks.lock().expect("scheduler poisoned").push(task);
+    }
+
+    /// The tasks that have not finished yet, in spawn order.
+    pub(crate) fn unfinished(&self) -> Vec<Arc<Task>> {
+        let mut tasks = self.tasks.lock().expect("scheduler poisoned");
+        tasks.retain(|task| !task.is_finished());
+        tasks.clone()
+    }
+
+    /// Failed tasks that no `join` has seen, in the order they failed.
+    pub(crate) fn unjoined_failures(&self) -> Vec<Arc<Task>> {
+        let failed = self.failed.lock().expect("scheduler poisoned");
+        failed.iter().filter(|task| !task.joined.load(Ordering::SeqCst)).cloned().collect()
+    }
+
+    pub(crate) fn progress(&self) -> usize {
+        self.progress.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn notify(&self) {
+        self.progress.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn finish(&self, task: &Arc<Task>, result: Result<Value, String>) {
+        if result.is_err() {
+            self.failed.lock().expect("scheduler poisoned").push(task.clone());
+        }
+        task.finish(result);
+        self.notify();
+    }
+
+    /// Unfinished tasks in the order they should be given a turn.
+    pub(crate) fn round(&self) -> Vec<Arc<Task>> {
+        let mut tasks = self.tasks.lock().expect("scheduler poisoned");
+        tasks.retain(|task| !task.is_finished());
+        let mut round = tasks.clone();
+        if !self.deterministic {
+            round.shuffle(&mut rand::thread_rng());
+        }
+        round
+    }
+}
@@ -0,0 +1,222 @@
+use crate::interpreter::Interpreter;
+use crate::stmt::Stmt;
+use crate::token::Tokensizer;
+use crate::{check_limits, compile, coroutine, Options};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// `aoi test`: runs every `.aoi` file that has `test "name" { ... }` blocks or
+// `// expect: ...` comments. Each `// expect:` comment gives one line the
+// program must print, in order, so a file documents its own output:
+//
+//   write(1 + 2);   // expect: 3
+//
+// Tasks run in spawn order, so the output is the same on every run.
+
+const EXPECT: &str = "// expect:";
+
+// What a file should print, with the source line of each expectation
+struct Expectation {
+    line: usize,
+    text: String,
+}
+
+enum Outcome {
+    Passed(String), // Summary such as "3 tests, 4 expected lines"
+    Failed(Vec<String>),
+    Skipped,
+}
+
+/// Runs the tests under `paths`, printing a report per file. Returns the exit status.
+pub fn run(paths: &[String]) -> i32 {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            collect_files(path, &mut files);
+        } else if path.exists() {
+            files.push(path.to_path_buf());
+        } else {
+            eprintln!("No such file or directory: {}", path.display());
+            return 2;
+        }
+    }
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for file in &files {
+        match test_file(file) {
+            Outcome::Passed(summary) => {
+                passed += 1;
+                println!("PASS {} ({})", file.display(), summary);
+            }
+            Outcome::Failed(problems) => {
+                failed += 1;
+                println!("FAIL {}", file.display());
+                for problem in problems {
+                    for line in problem.lines() {
+                        println!("    {}", line);
+                    }
+                }
+            }
+            Outcome::Skipped => skipped += 1,
+        }
+    }
+
+    if passed + failed == 0 {
+        println!("No tests found.");
+        return 0;
+    }
+    println!();
+    println!("{} passed, {} failed, {} without tests.", passed, failed, skipped);
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// `.aoi` files below `dir`, sorted, leaving out hidden and build directories
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                collect_files(&path, files);
+            }
+        } else if path.extension().is_some_and(|extension| extension == "aoi") {
+            files.push(path);
+        }
+    }
+}
+
+fn test_file(file: &Path) -> Outcome {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(e) => return Outcome::Failed(vec![format!("Cannot read file: {}", e)]),
+    };
+    let expectations = expectations(&source);
+    if expectations.is_empty() && !source.contains("test \"") {
+        return Outcome::Skipped; // Quick check before parsing
+    }
+
+    if let Err(error) = check_limits(&source) {
+        return Outcome::Failed(vec![error]);
+    }
+    let options = Options {
+        deterministic: true,
+        ..Options::default()
+    };
+    let statements = match compile(&source, &options) {
+        Ok(statements) => statements,
+        Err(errors) => return Outcome::Failed(vec![errors]),
+    };
+    let test_count = statements.iter().filter(|stmt| matches!(stmt, Stmt::Test { .. })).count();
+    if expectations.is_empty() && test_count == 0 {
+        return Outcome::Skipped;
+    }
+
+    let (output, status, results) = coroutine::on_large_stack(|| {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_deterministic(true);
+        interpreter.set_test_mode(true);
+        let output = interpreter.interpret(&statements);
+        let results: Vec<(String, Option<String>)> = interpreter
+            .test_results()
+            .iter()
+            .map(|result| (result.name.clone(), result.error.clone()))
+            .collect();
+        (output, interpreter.exit_status(), results)
+    });
+
+    let mut problems = Vec::new();
+    for (name, error) in &results {
+        if let Some(error) = error {
+            problems.push(format!("test \"{}\" failed: {}", name, error));
+        }
+    }
+
+    let actual: Vec<&str> = output.lines().collect();
+    if !expectations.is_empty() {
+        if let Some(diff) = diff(&expectations, &actual) {
+            problems.push(format!("Output differs from the expectations (- expected, + actual):\n{}", diff));
+        }
+    } else if status != 0 {
+        // Without expectations, errors in the output are failures
+        let mut problem = format!("Exited with status {}.", status);
+        for line in actual.iter().filter(|line| line.starts_with("Runtime error")) {
+            problem.push_str(&format!("\n{}", line));
+        }
+        problems.push(problem);
+    }
+
+    if !problems.is_empty() {
+        return Outcome::Failed(problems);
+    }
+    let mut summary = Vec::new();
+    if !results.is_empty() {
+        summary.push(format!("{} test{}", results.len(), if results.len() == 1 { "" } else { "s" }));
+    }
+    if !expectations.is_empty() {
+        let count = expectations.len();
+        summary.push(format!("{} expected line{}", count, if count == 1 { "" } else { "s" }));
+    }
+    Outcome::Passed(summary.join(", "))
+}
+
+// Comments come from the tokenizer, so `// expect:` inside a string is not one
+fn expectations(source: &str) -> Vec<Expectation> {
+    let mut tokenizer = Tokensizer::new(source.to_string());
+    let _ = tokenizer.tokenize(); // `compile` reports errors; the comments are found either way
+    tokenizer
+        .comments()
+        .iter()
+        .filter_map(|(line, comment)| {
+            let text = comment.strip_prefix(EXPECT)?.trim_end_matches('\r');
+            Some(Expectation {
+                line: *line,
+                text: text.strip_prefix(' ').unwrap_or(text).to_string(),
+            })
+        })
+        .collect()
+}
+
+// Line diff of the expected and actual output, `None` when they match. Lines
+// are matched up by their longest common subsequence.
+fn diff(expected: &[Expectation], actual: &[&str]) -> Option<String> {
+    let (n, m) = (expected.len(), actual.len());
+    // common[i][j]: length of the longest common subsequence of expected[i..] and actual[j..]
+    let mut common = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if expected[i].text == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    if common[0][0] == n && n == m {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i].text == actual[j] {
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}  (line {})", expected[i].text, expected[i].line));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    Some(lines.join("\n"))
+}
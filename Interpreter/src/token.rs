@@ -1,10 +1,12 @@
 use crate::error;
+use rust_decimal::Decimal;
 use std::fmt::Display;
 #[derive(Debug, Clone, PartialEq)] //	Allows println!("{:?}", obj); for debugging.  Allows obj.clone(); for copying data.
 #[allow(dead_code)] //Prevents warnings for unused code.
 pub enum TokenLiteral {
     String(String),
     Number(f64),
+    Decimal(Decimal), // `0.1d`
     Identifier(String),
     Boolean(bool),
     Null,
@@ -20,6 +22,7 @@ impl Display for TokenLiteral {
                     write!(f, "{}", n) // If decimal, show normally (e.g., 12.34)
                 }
             }
+            TokenLiteral::Decimal(d) => write!(f, "{}d", d),
             TokenLiteral::Boolean(b) => write!(f, "{}", b),
             TokenLiteral::Identifier(s) => write!(f, "{}", s),
             TokenLiteral::Null => write!(f, "null"),
@@ -31,6 +34,8 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<TokenLiteral>,
+    pub line: usize, // Source line, 0 for tokens made up by the interpreter
+    pub doc: Option<String>, // `///` comment lines right before this token
 }
 impl Token {
     pub fn new(token_type: TokenType, lexeme: String, literal: TokenLiteral) -> Self {
@@ -38,6 +43,8 @@ impl Token {
             token_type,
             lexeme,
             literal: Some(literal),
+            line: 0,
+            doc: None,
         }
     }
 }
@@ -50,7 +57,7 @@ impl Display for Token {
         )
     }
 }
-#[allow(dead_code, non_camel_case_types)]
+#[allow(dead_code, non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.
@@ -61,12 +68,15 @@ pub enum TokenType {
     RIGHT_BRACKET,
     LEFT_BRACKET,
     COMMA,
+    COLON,
     DOT,
     MINUS,
     PLUS,
     SEMICOLON,
     SLASH,
     STAR,
+    STAR_STAR,
+    PERCENT,
     // One or two character tokens.
     BANG,
     BANG_EQUAL,
@@ -76,6 +86,18 @@ pub enum TokenType {
     GREATER_EQUAL,
     LESS,
     LESS_EQUAL,
+    FAT_ARROW,
+    DOT_DOT,
+    DOT_DOT_EQUAL,
+    DOT_DOT_DOT,
+    AMPERSAND,
+    PIPE,
+    CARET,
+    TILDE,
+    LESS_LESS,
+    GREATER_GREATER,
+    QUESTION_QUESTION,
+    QUESTION_BRACKET,
 
     // Literals.
     IDENTIFIER,
@@ -93,52 +115,78 @@ pub enum TokenType {
     NIL,
     OR,
     PRINT,
+    PRINT_INLINE,
+    PRINT_ERROR,
     RETURN,
     SUPER,
     THIS,
     TRUE,
     VAR,
+    CONST,
     WHILE,
+    MATCH,
+    IN,
+    ENUM,
+    YIELD,
     EOF,
     SCAN,
 }
 pub struct Tokensizer {
     src: String,
+    chars: Vec<char>, // `start` and `current` index characters, not bytes
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    doc: Vec<String>, // `///` lines waiting for the next token
+    comments: Vec<(usize, String)>, // Every `//` comment with its line, for `aoi test`
+    errors: Vec<String>,
 }
 impl Tokensizer {
     pub fn new(src: String) -> Self {
         Self {
+            chars: src.chars().collect(),
             src,
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            doc: Vec::new(),
+            comments: Vec::new(),
+            errors: Vec::new(),
         }
     }
     fn is_at_end(&self) -> bool {
-        self.current >= self.src.len()
+        self.current >= self.chars.len()
+    }
+
+    fn text(&self, from: usize, to: usize) -> String {
+        self.chars[from..to].iter().collect()
     }
 
     fn add_token(&mut self, token_type: TokenType, literal: TokenLiteral) {
-        let text = self.src[self.start..self.current].to_string();
-        self.tokens.push(Token::new(token_type, text, literal));
+        let text = self.text(self.start, self.current);
+        let mut token = Token::new(token_type, text, literal);
+        token.line = self.line;
+        if !self.doc.is_empty() {
+            token.doc = Some(self.doc.join("\n"));
+            self.doc.clear();
+        }
+        self.tokens.push(token);
     }
 
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.src.chars().nth(self.current - 1).unwrap()
+        self.chars[self.current - 1]
     }
 
     fn peek(&self) -> Option<char> {
-        self.src.chars().nth(self.current)
+        self.chars.get(self.current).copied()
     }
 
     //this function is used to scan the string
     fn string(&mut self) {
+        let opening_line = self.line;
         while self.peek() != Some('"') && !self.is_at_end() {
             if self.peek() == Some('\n') {
                 self.line += 1;
@@ -147,13 +195,13 @@ impl Tokensizer {
         }
 
         if self.is_at_end() {
-            error::error(self.line, "Unterminated string", "");
+            self.error(opening_line, "Unterminated string", &self.source_line(opening_line));
             return;
         }
         self.advance();
 
         // Extract the string value without the surrounding quotes
-        let value = self.src[self.start + 1..self.current - 1].to_string();
+        let value = self.text(self.start + 1, self.current - 1);
         self.add_token(TokenType::STRING, TokenLiteral::String(value));
     }
 
@@ -162,44 +210,59 @@ impl Tokensizer {
     }
 
     fn number(&mut self) {
-        while self.peek().map_or(false, Self::isdigit) {
+        // `0x`, `0b` and `0o` prefixes
+        if self.chars[self.start] == '0' && matches!(self.peek(), Some('x' | 'X' | 'b' | 'B' | 'o' | 'O')) {
             self.advance();
-        }
+            while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+                self.advance();
+            }
+        } else {
+            self.digits();
 
-        // Look for a fractional part
-        let mut _has_fraction = false;
-        if self.peek() == Some('.') {
-            // Ensure the next character is a digit before consuming the dot
-            if self.peek_next().map_or(false, Self::isdigit) {
-                _has_fraction = true;
+            // Look for a fractional part
+            if self.peek() == Some('.') && self.peek_next().is_some_and(Self::isdigit) {
                 self.advance(); // Consume the '.'
+                self.digits();
+            }
 
-                while self.peek().map_or(false, Self::isdigit) {
-                    self.advance();
+            // Scientific notation, e.g. `1e9` or `2.5E-3`
+            if matches!(self.peek(), Some('e' | 'E')) {
+                let sign = matches!(self.peek_next(), Some('+' | '-'));
+                let exponent = self.chars.get(self.current + if sign { 2 } else { 1 });
+                if exponent.is_some_and(|c| c.is_ascii_digit()) {
+                    self.current += if sign { 2 } else { 1 };
+                    self.digits();
                 }
             }
         }
 
-        // Convert the lexeme to a floating-point number
+        let lexeme = self.text(self.start, self.current);
 
-        // If no fractional part exists, ensure .0 is appended
-        // Convert the lexeme to a floating-point number
-        let lexeme = &self.src[self.start..self.current];
-        let float_lexeme = if lexeme.contains('.') {
-            lexeme.to_string()
-        } else {
-            format!("{}.0", lexeme) // Append .0 if it's an integer
-        };
+        // A `d` suffix makes an exact decimal, e.g. `19.99d`
+        if self.peek() == Some('d') && !self.peek_next().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            self.advance();
+            match parse_decimal(&lexeme) {
+                Some(value) => self.add_token(TokenType::NUMBER, TokenLiteral::Decimal(value)),
+                None => self.error(self.line, &format!("Invalid decimal number: {}d", lexeme), &self.source_line(self.line)),
+            }
+            return;
+        }
 
-        if let Ok(value) = float_lexeme.parse::<f64>() {
-            self.add_token(TokenType::NUMBER, TokenLiteral::Number(value));
-        } else {
-            error::error(self.line, &format!("Invalid number format: {}", lexeme), "");
+        match parse_number(&lexeme) {
+            Some(value) => self.add_token(TokenType::NUMBER, TokenLiteral::Number(value)),
+            None => self.error(self.line, &format!("Invalid number format: {}", lexeme), &self.source_line(self.line)),
+        }
+    }
+
+    // Digits with optional `_` separators, as in `1_000_000`
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|c| Self::isdigit(c) || c == '_') {
+            self.advance();
         }
     }
 
     fn peek_next(&self) -> Option<char> {
-        self.src.chars().nth(self.current + 1)
+        self.chars.get(self.current + 1).copied()
     }
 
     fn isalpha(c: char) -> bool {
@@ -207,12 +270,12 @@ impl Tokensizer {
     }
 
     fn identifier(&mut self) {
-        while self.peek().map_or(false, Self::isalpha) {
+        while self.peek().is_some_and(Self::isalpha) {
             self.advance();
         }
 
-        let text = &self.src[self.start..self.current];
-        let token_type = match text {
+        let text = self.text(self.start, self.current);
+        let token_type = match text.as_str() {
             "and" => TokenType::AND,
             "class" => TokenType::CLASS,
             "else" => TokenType::ELSE,
@@ -223,12 +286,19 @@ impl Tokensizer {
             "nil" => TokenType::NIL,
             "or" => TokenType::OR,
             "write" => TokenType::PRINT,
+            "write_inline" => TokenType::PRINT_INLINE,
+            "ewrite" => TokenType::PRINT_ERROR,
             "return" => TokenType::RETURN,
             "super" => TokenType::SUPER,
             "this" => TokenType::THIS,
             "true" => TokenType::TRUE,
             "var" => TokenType::VAR,
+            "const" => TokenType::CONST,
+            "enum" => TokenType::ENUM,
+            "yield" => TokenType::YIELD,
             "while" => TokenType::WHILE,
+            "match" => TokenType::MATCH,
+            "in" => TokenType::IN,
             "scan" => TokenType::SCAN,
             _ => TokenType::IDENTIFIER,
         };
@@ -246,34 +316,73 @@ impl Tokensizer {
             '{' => self.add_token(TokenType::LEFT_BRACE, TokenLiteral::Null),
             '}' => self.add_token(TokenType::RIGHT_BRACE, TokenLiteral::Null),
             ',' => self.add_token(TokenType::COMMA, TokenLiteral::Null),
+            ':' => self.add_token(TokenType::COLON, TokenLiteral::Null),
             '.' => {
-                if self.peek().map_or(false, |c| c.is_digit(10)) {
+                if self.peek().is_some_and(|c| c.is_ascii_digit()) {
                     self.advance(); // Consume '.'
-                    while self.peek().map_or(false, |c| c.is_digit(10)) {
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
                         self.advance();
                     }
 
-                    let value: f64 = self.src[self.start..self.current].parse().unwrap();
+                    let value: f64 = self.text(self.start, self.current).parse().unwrap();
                     self.add_token(TokenType::NUMBER, TokenLiteral::Number(value));
+                } else if self.peek() == Some('.') {
+                    self.current += 1;
+                    if self.peek() == Some('.') {
+                        self.current += 1;
+                        self.add_token(TokenType::DOT_DOT_DOT, TokenLiteral::Null);
+                    } else if self.peek() == Some('=') {
+                        self.current += 1;
+                        self.add_token(TokenType::DOT_DOT_EQUAL, TokenLiteral::Null);
+                    } else {
+                        self.add_token(TokenType::DOT_DOT, TokenLiteral::Null);
+                    }
                 } else {
                     self.add_token(TokenType::DOT, TokenLiteral::Null);
                 }
             }
 
+            '?' => {
+                if self.peek() == Some('?') {
+                    self.current += 1;
+                    self.add_token(TokenType::QUESTION_QUESTION, TokenLiteral::Null);
+                } else if self.peek() == Some('[') {
+                    self.current += 1;
+                    self.add_token(TokenType::QUESTION_BRACKET, TokenLiteral::Null);
+                } else {
+                    self.unexpected_character(c);
+                }
+            }
+
             '-' => self.add_token(TokenType::MINUS, TokenLiteral::Null),
             '+' => self.add_token(TokenType::PLUS, TokenLiteral::Null),
             ';' => self.add_token(TokenType::SEMICOLON, TokenLiteral::Null),
-            '*' => self.add_token(TokenType::STAR, TokenLiteral::Null),
+            '*' => {
+                if self.peek() == Some('*') {
+                    self.current += 1;
+                    self.add_token(TokenType::STAR_STAR, TokenLiteral::Null);
+                } else {
+                    self.add_token(TokenType::STAR, TokenLiteral::Null);
+                }
+            }
+            '%' => self.add_token(TokenType::PERCENT, TokenLiteral::Null),
+            '&' => self.add_token(TokenType::AMPERSAND, TokenLiteral::Null),
+            '|' => self.add_token(TokenType::PIPE, TokenLiteral::Null),
+            '^' => self.add_token(TokenType::CARET, TokenLiteral::Null),
+            '~' => self.add_token(TokenType::TILDE, TokenLiteral::Null),
             '=' => {
-                if self.src.chars().nth(self.current).unwrap() == '=' {
+                if self.peek() == Some('=') {
                     self.current += 1;
                     self.add_token(TokenType::EQUAL_EQUAL, TokenLiteral::Null);
+                } else if self.peek() == Some('>') {
+                    self.current += 1;
+                    self.add_token(TokenType::FAT_ARROW, TokenLiteral::Null);
                 } else {
                     self.add_token(TokenType::EQUAL, TokenLiteral::Null);
                 }
             }
             '!' => {
-                if self.src.chars().nth(self.current).unwrap() == '=' {
+                if self.peek() == Some('=') {
                     //check the next one if it is equal then add the token
                     self.current += 1;
                     self.add_token(TokenType::BANG_EQUAL, TokenLiteral::Null);
@@ -283,31 +392,45 @@ impl Tokensizer {
                 }
             }
             '<' => {
-                if self.src.chars().nth(self.current) == Some('=') {
+                if self.peek() == Some('=') {
                     // ✅ Safe check
                     self.current += 1;
                     self.add_token(TokenType::LESS_EQUAL, TokenLiteral::Null);
+                } else if self.peek() == Some('<') {
+                    self.current += 1;
+                    self.add_token(TokenType::LESS_LESS, TokenLiteral::Null);
                 } else {
                     self.add_token(TokenType::LESS, TokenLiteral::Null);
                 }
             }
 
             '>' => {
-                if self.src.chars().nth(self.current) == Some('=') {
+                if self.peek() == Some('=') {
                     //check the next one if it is equal then add the token
                     self.current += 1;
                     self.add_token(TokenType::GREATER_EQUAL, TokenLiteral::Null);
+                } else if self.peek() == Some('>') {
+                    self.current += 1;
+                    self.add_token(TokenType::GREATER_GREATER, TokenLiteral::Null);
                 } else {
                     //else add Greater token
                     self.add_token(TokenType::GREATER, TokenLiteral::Null);
                 }
             }
             '/' => {
-                if self.src.chars().nth(self.current) == Some('/') {
+                if self.peek() == Some('/') {
                     //check the next one if it is equal then add the token used some because it returns an option and we are getting initially none as unwrap cant be none
-                    while self.src.chars().nth(self.current) != Some('\n') && !self.is_at_end() {
+                    while self.peek() != Some('\n') && !self.is_at_end() {
                         self.current += 1;
                     }
+                    // `///` documents the declaration that follows; `////` is a plain comment
+                    let comment = self.text(self.start, self.current);
+                    if let Some(text) = comment.strip_prefix("///").filter(|text| !text.starts_with('/')) {
+                        self.doc.push(text.strip_prefix(' ').unwrap_or(text).trim_end().to_string());
+                    }
+                    self.comments.push((self.line, comment));
+                } else if self.peek() == Some('*') {
+                    self.block_comment();
                 } else {
                     self.add_token(TokenType::SLASH, TokenLiteral::Null);
                 }
@@ -326,31 +449,73 @@ impl Tokensizer {
 
             _ if Self::isalpha(c) => self.identifier(),
 
-            _ => {
-                let line_content: String = self
-                    .src
-                    .lines()
-                    .nth(self.line - 1)
-                    .unwrap_or("")
-                    .to_string();
-
-                error::error(
-                    self.line,
-                    &format!("Unexpected character: '{}'", c),
-                    &line_content,
-                );
+            _ => self.unexpected_character(c),
+        }
+    }
+
+    // `/* ... */`, which may contain other block comments
+    fn block_comment(&mut self) {
+        let opening_line = self.line;
+        self.advance(); // The '*'
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.peek(), self.peek_next()) {
+                (None, _) => {
+                    self.error(opening_line, "Unterminated block comment", &self.source_line(opening_line));
+                    return;
+                }
+                (Some('/'), Some('*')) => {
+                    self.current += 2;
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.current += 2;
+                    depth -= 1;
+                }
+                (Some(c), _) => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    self.current += 1;
+                }
             }
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    // Errors are collected, so one run reports them all and `tokenize` can fail
+    fn error(&mut self, line: usize, message: &str, context: &str) {
+        self.errors.push(error::format(line, message, context));
+    }
+
+    fn source_line(&self, line: usize) -> String {
+        self.src.lines().nth(line - 1).unwrap_or("").to_string()
+    }
+
+    fn unexpected_character(&mut self, c: char) {
+        self.error(
+            self.line,
+            &format!("Unexpected character: '{}'", c),
+            &self.source_line(self.line),
+        );
+    }
+
+    /// Splits the source into tokens, or gives every error found in it.
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, Vec<String>> {
         while !self.is_at_end() {
             self.start = self.current;
             self.scan_token();
         }
-        self.tokens
-            .push(Token::new(TokenType::EOF, "".into(), TokenLiteral::Null));
-        self.tokens.clone()
+        if !self.errors.is_empty() {
+            return Err(self.errors.clone());
+        }
+        let mut eof = Token::new(TokenType::EOF, "".into(), TokenLiteral::Null);
+        eof.line = self.line;
+        self.tokens.push(eof);
+        Ok(self.tokens.clone())
+    }
+    /// The `//` comments found by `tokenize`, from the `//` on, with their lines.
+    pub fn comments(&self) -> &[(usize, String)] {
+        &self.comments
     }
     // pub fn print_tokens(&self) {
     //     for token in &self.tokens {
@@ -358,3 +523,40 @@ impl Tokensizer {
     //     }
     // }
 }
+
+/// Reads a number literal: decimal with optional fraction and exponent, or
+/// `0x`/`0b`/`0o` integers. `_` may separate digits.
+pub fn parse_number(lexeme: &str) -> Option<f64> {
+    let (digits, radix) = match lexeme.get(..2) {
+        Some("0x" | "0X") => (&lexeme[2..], 16),
+        Some("0b" | "0B") => (&lexeme[2..], 2),
+        Some("0o" | "0O") => (&lexeme[2..], 8),
+        _ => (lexeme, 10),
+    };
+
+    // Every separator must sit between two digits
+    let chars: Vec<char> = digits.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        let is_digit = |c: Option<&char>| c.is_some_and(|c| c.is_digit(radix));
+        if *c == '_' && !(i > 0 && is_digit(chars.get(i - 1)) && is_digit(chars.get(i + 1))) {
+            return None;
+        }
+    }
+    let digits = digits.replace('_', "");
+
+    if radix == 10 {
+        digits.parse::<f64>().ok()
+    } else {
+        u64::from_str_radix(&digits, radix).ok().map(|n| n as f64)
+    }
+}
+
+/// Reads the digits of a decimal literal such as `19.99` or `1_000.5`.
+pub fn parse_decimal(lexeme: &str) -> Option<Decimal> {
+    let radix_prefix = matches!(lexeme.get(..2), Some("0x" | "0X" | "0b" | "0B" | "0o" | "0O"));
+    parse_number(lexeme).filter(|_| !radix_prefix)?; // Same separator rules as other numbers
+    let digits = lexeme.replace('_', "");
+    Decimal::from_str_exact(&digits)
+        .or_else(|_| Decimal::from_scientific(&digits))
+        .ok()
+}
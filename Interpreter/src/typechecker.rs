@@ -0,0 +1,852 @@
+use crate::expr::{Expr, Pattern};
+use crate::stmt::Stmt;
+use crate::token::{Token, TokenLiteral, TokenType};
+use crate::types::{Signature, Type};
+use std::collections::{HashMap, HashSet};
+
+// Upper bound on inference passes; types only ever widen, so this is rarely reached
+const MAX_PASSES: usize = 8;
+
+// A `var`, `const`, parameter, function or binding
+struct Declaration {
+    ty: Type,
+    annotated: bool, // Annotated names keep their type; others widen with every assignment
+}
+
+// Gradual type checker run after parsing. Values are checked against
+// annotations, and unannotated locals are given the type of every value stored
+// in them. A variable that holds different kinds of values becomes `any`, so
+// only mistakes that would certainly fail at runtime are reported.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, usize>>, // Name -> index into `declarations`
+    declarations: Vec<Declaration>,
+    inferred: Vec<Type>, // Types from the previous pass, by declaration order
+    unresolved: HashMap<String, Type>, // Values assigned to names not declared yet
+    earlier_unresolved: HashMap<String, Type>,
+    returns: Vec<Option<Type>>, // Declared return type of each enclosing function
+    enums: HashSet<String>,
+    errors: Vec<String>,
+    line: usize, // Line of the most recent token, for expressions without one
+    strict: bool, // Conditions must be booleans
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            declarations: Vec::new(),
+            inferred: Vec::new(),
+            unresolved: HashMap::new(),
+            earlier_unresolved: HashMap::new(),
+            returns: Vec::new(),
+            enums: HashSet::new(),
+            errors: Vec::new(),
+            line: 0,
+            strict: false,
+        }
+    }
+
+    /// Reports non-boolean conditions, as the interpreter's strict mode rejects them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Checks a whole program, returning one message per type error found.
+    pub fn check(mut self, statements: &[Stmt]) -> Vec<String> {
+        collect_enums(statements, &mut self.enums);
+
+        // Re-check until the inferred types stop widening; the last pass reports
+        for _ in 0..MAX_PASSES {
+            self.scopes = vec![HashMap::new()];
+            self.declarations.clear();
+            self.errors.clear();
+            self.earlier_unresolved = std::mem::take(&mut self.unresolved);
+
+            for stmt in statements {
+                self.check_stmt(stmt);
+            }
+
+            let types: Vec<Type> = self.declarations.iter().map(|d| d.ty.clone()).collect();
+            let settled = types == self.inferred && self.unresolved == self.earlier_unresolved;
+            self.inferred = types;
+            if settled {
+                break;
+            }
+        }
+        self.errors
+    }
+
+    fn error(&mut self, line: usize, message: String) {
+        self.errors
+            .push(format!("[line {}] Type error: {}", line, message));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, ty: Type, annotated: bool) {
+        let index = self.declarations.len();
+        let ty = if annotated {
+            ty
+        } else {
+            let mut ty = stored(&ty);
+            if let Some(earlier) = self.inferred.get(index) {
+                ty = ty.join(earlier);
+            }
+            if self.scopes.len() == 1 {
+                if let Some(assigned) = self.earlier_unresolved.get(&name.lexeme) {
+                    ty = ty.join(assigned);
+                }
+            }
+            ty
+        };
+        self.declarations.push(Declaration { ty, annotated });
+        self.scopes
+            .last_mut()
+            .expect("checker always has a scope")
+            .insert(name.lexeme.clone(), index);
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn variable_type(&self, name: &str) -> Type {
+        match self.lookup(name) {
+            Some(index) => self.declarations[index].ty.clone(),
+            None => builtin_type(name),
+        }
+    }
+
+    fn assign(&mut self, name: &Token, ty: &Type) {
+        self.line = name.line;
+        match self.lookup(&name.lexeme) {
+            Some(index) if self.declarations[index].annotated => {
+                let expected = self.declarations[index].ty.clone();
+                if !ty.fits(&expected) {
+                    let message = format!(
+                        "Cannot assign {} to '{}' of type {}.",
+                        ty, name.lexeme, expected
+                    );
+                    self.error(name.line, message);
+                }
+            }
+            Some(index) => {
+                let widened = self.declarations[index].ty.join(&stored(ty));
+                self.declarations[index].ty = widened;
+            }
+            None => {
+                let widened = match self.unresolved.get(&name.lexeme) {
+                    Some(earlier) => earlier.join(&stored(ty)),
+                    None => stored(ty),
+                };
+                self.unresolved.insert(name.lexeme.clone(), widened);
+            }
+        }
+    }
+
+    // The type an annotation stands for. An unknown name is reported once and
+    // then checked as `Any`, so it does not cause further errors.
+    fn check_annotation(&mut self, ty: &Type, line: usize) -> Type {
+        match ty {
+            Type::Named(name) if !self.enums.contains(name) => {
+                self.error(line, format!("Unknown type '{}'.", name));
+                Type::Any
+            }
+            Type::Array(element) => Type::Array(Box::new(self.check_annotation(element, line))),
+            _ => ty.clone(),
+        }
+    }
+
+    // Declares a name holding a checked value, with or without an annotation
+    fn declare_checked(
+        &mut self,
+        name: &Token,
+        annotation: &Option<Type>,
+        initializer: Option<&Expr>,
+        what: &str,
+    ) {
+        self.line = name.line;
+        let annotation = annotation.as_ref().map(|ty| self.check_annotation(ty, name.line));
+        let value = initializer.map(|init| self.check_expr_as(init, annotation.as_ref()));
+        match annotation {
+            Some(annotation) => {
+                match value {
+                    Some(value) if !value.fits(&annotation) => {
+                        let message = format!(
+                            "Cannot initialize {} '{}' of type {} with {}.",
+                            what, name.lexeme, annotation, value
+                        );
+                        self.error(name.line, message);
+                    }
+                    // It would start out as `nil`
+                    None if !Type::Nil.fits(&annotation) => {
+                        let message = format!(
+                            "Variable '{}' of type {} needs an initial value.",
+                            name.lexeme, annotation
+                        );
+                        self.error(name.line, message);
+                    }
+                    _ => {}
+                }
+                self.declare(name, annotation, true);
+            }
+            None => self.declare(name, value.unwrap_or(Type::Unknown), false),
+        }
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => self.declare(name, Type::Any, false),
+            Pattern::Array { elements, rest } => {
+                for element in elements {
+                    self.declare_pattern(element);
+                }
+                if let Some(rest) = rest {
+                    self.declare(rest, Type::Array(Box::new(Type::Any)), false);
+                }
+            }
+            Pattern::Variant { fields, .. } => {
+                for field in fields {
+                    self.declare_pattern(field);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } => {}
+        }
+    }
+
+    // Any value can be a condition, except in strict mode
+    fn check_condition(&mut self, condition: &Expr) -> Type {
+        let ty = self.check_expr(condition);
+        self.require_bool(&ty);
+        ty
+    }
+
+    fn require_bool(&mut self, ty: &Type) {
+        if self.strict && ty.is_known() && *ty != Type::Bool {
+            let line = self.line;
+            self.error(line, format!("Condition must be a boolean but got {}.", ty));
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression } => {
+                self.check_expr(expression);
+            }
+            Stmt::Print { expressions, separator, .. } => {
+                for expression in expressions.iter().chain(separator) {
+                    self.check_expr(expression);
+                }
+            }
+            Stmt::Var {
+                name,
+                annotation,
+                initializer,
+            } => {
+                self.declare_checked(name, annotation, initializer.as_ref(), "variable");
+            }
+            Stmt::Const {
+                name,
+                annotation,
+                initializer,
+            } => {
+                self.declare_checked(name, annotation, Some(initializer), "constant");
+            }
+            Stmt::Destructure {
+                pattern,
+                initializer,
+            } => {
+                self.check_expr(initializer);
+                self.declare_pattern(pattern);
+            }
+            Stmt::Block(statements) | Stmt::Test { body: statements, .. } => {
+                self.begin_scope();
+                for stmt in statements {
+                    self.check_stmt(stmt);
+                }
+                self.end_scope();
+            }
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.line = keyword.line;
+                self.check_condition(condition);
+                self.check_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_stmt(else_branch);
+                }
+            }
+            Stmt::While { keyword, condition, body } => {
+                self.line = keyword.line;
+                self.check_condition(condition);
+                self.check_stmt(body);
+            }
+            Stmt::Input { name } => self.assign(name, &Type::Any),
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.begin_scope();
+                if let Some(init) = initializer {
+                    self.check_stmt(init);
+                }
+                if let Some(cond) = condition {
+                    self.check_condition(cond);
+                }
+                if let Some(inc) = increment {
+                    self.check_expr(inc);
+                }
+                self.check_stmt(body);
+                self.end_scope();
+            }
+            Stmt::ForIn {
+                index,
+                variable,
+                iterable,
+                body,
+            } => {
+                let element = match self.check_expr(iterable) {
+                    Type::Range => Type::Num,
+                    Type::Str => Type::Str,
+                    Type::Array(element) => *element,
+                    _ => Type::Any,
+                };
+                self.begin_scope();
+                if let Some(index) = index {
+                    self.declare(index, Type::Num, false);
+                }
+                self.declare(variable, element, false);
+                self.check_stmt(body);
+                self.end_scope();
+            }
+            Stmt::Function {
+                name,
+                params,
+                param_types,
+                return_type,
+                body,
+                generator,
+                ..
+            } => {
+                self.line = name.line;
+                let param_types: Vec<Option<Type>> = param_types
+                    .iter()
+                    .map(|ty| ty.as_ref().map(|ty| self.check_annotation(ty, name.line)))
+                    .collect();
+                let return_type = return_type.as_ref().map(|ty| self.check_annotation(ty, name.line));
+
+                let declared_return = if *generator {
+                    None
+                } else {
+                    return_type
+                };
+                let signature = Signature {
+                    params: Some(
+                        param_types
+                            .iter()
+                            .map(|t| t.clone().unwrap_or(Type::Any))
+                            .collect(),
+                    ),
+                    ret: declared_return.clone().unwrap_or(Type::Any),
+                };
+                self.declare(name, Type::Function(Some(Box::new(signature))), false);
+
+                self.begin_scope();
+                for (param, annotation) in params.iter().zip(&param_types) {
+                    self.declare(param, annotation.clone().unwrap_or(Type::Any), true);
+                }
+                self.returns.push(declared_return);
+                for stmt in body {
+                    self.check_stmt(stmt);
+                }
+                self.returns.pop();
+                self.end_scope();
+            }
+            Stmt::Return { keyword, value } => {
+                self.line = keyword.line;
+                let expected = self.returns.last().cloned().flatten();
+                let ty = value.as_ref().map(|value| self.check_expr_as(value, expected.as_ref()));
+                let Some(expected) = expected else {
+                    return;
+                };
+                match ty {
+                    Some(ty) if !ty.fits(&expected) => {
+                        let message = format!(
+                            "Cannot return {} from a function declared to return {}.",
+                            ty, expected
+                        );
+                        self.error(keyword.line, message);
+                    }
+                    None if !Type::Nil.fits(&expected) => {
+                        let message = format!("Expected a return value of type {}.", expected);
+                        self.error(keyword.line, message);
+                    }
+                    _ => {}
+                }
+            }
+            Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+            }
+            Stmt::Match { subject, arms } => {
+                self.check_expr(subject);
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.check_condition(guard);
+                    }
+                    self.check_stmt(&arm.body);
+                    self.end_scope();
+                }
+            }
+            Stmt::Enum { name, .. } => self.declare(name, Type::Any, false),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(literal) => match literal.value.downcast_ref::<TokenLiteral>() {
+                Some(TokenLiteral::Number(_) | TokenLiteral::Decimal(_)) => Type::Num,
+                Some(TokenLiteral::String(_)) => Type::Str,
+                Some(TokenLiteral::Boolean(_)) => Type::Bool,
+                Some(TokenLiteral::Null) => Type::Nil,
+                _ if literal.value.is::<bool>() => Type::Bool,
+                _ => Type::Any,
+            },
+            Expr::Variable(variable) => {
+                self.line = variable.name.line;
+                self.variable_type(&variable.name.lexeme)
+            }
+            Expr::Assign(name, value) => {
+                self.line = name.line;
+                let expected = self
+                    .lookup(&name.lexeme)
+                    .filter(|&index| self.declarations[index].annotated)
+                    .map(|index| self.declarations[index].ty.clone());
+                let ty = self.check_expr_as(value, expected.as_ref());
+                self.assign(name, &ty);
+                ty
+            }
+            Expr::Grouping(group) => self.check_expr(&group.expression),
+            Expr::Unary(unary) => {
+                let operand = self.check_expr(&unary.right);
+                self.line = unary.operator.line;
+                match unary.operator.token_type {
+                    TokenType::MINUS | TokenType::TILDE => {
+                        if operand.is_known() && operand != Type::Num {
+                            let message = format!(
+                                "Operator '{}' expects a number but got {}.",
+                                unary.operator.lexeme, operand
+                            );
+                            self.error(unary.operator.line, message);
+                        }
+                        Type::Num
+                    }
+                    _ => {
+                        self.require_bool(&operand);
+                        Type::Bool
+                    }
+                }
+            }
+            Expr::Binary(binary) => {
+                let left = self.check_expr(&binary.left);
+                let right = self.check_expr(&binary.right);
+                self.check_binary(&binary.operator, &left, &right)
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                if operator.token_type != TokenType::QUESTION_QUESTION {
+                    // Either operand may be the result
+                    self.line = operator.line;
+                    let left = self.check_condition(left);
+                    self.line = operator.line;
+                    let right = self.check_condition(right);
+                    return left.join(&right);
+                }
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                match left {
+                    Type::Nil => right,
+                    left if left.is_known() => left, // Never nil, so `??` keeps it
+                    left => left.join(&right),
+                }
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.check_condition(condition);
+                let then_type = self.check_expr(then_branch);
+                match else_branch {
+                    Some(else_branch) => then_type.join(&self.check_expr(else_branch)),
+                    None => then_type.join(&Type::Nil),
+                }
+            }
+            Expr::Call { callee, arguments } => self.check_call(callee, arguments),
+            Expr::Array { elements } => {
+                let mut element = Type::Unknown;
+                for item in elements {
+                    element = element.join(&self.check_expr(item));
+                }
+                Type::Array(Box::new(element))
+            }
+            Expr::Index { object, index } => {
+                let object = self.check_expr(object);
+                let index = self.check_expr(index);
+                if index == Type::Range {
+                    // `arr[1..3]` is a slice
+                    return match object {
+                        Type::Array(_) | Type::Str => object,
+                        _ => Type::Any,
+                    };
+                }
+                if index.is_known() && index != Type::Num {
+                    let line = self.line;
+                    self.error(
+                        line,
+                        format!("Index must be a number or range but got {}.", index),
+                    );
+                }
+                match object {
+                    Type::Array(element) => *element,
+                    Type::Str => Type::Str,
+                    ty if ty.is_known() => {
+                        let line = self.line;
+                        self.error(line, format!("Cannot index {}.", ty));
+                        Type::Any
+                    }
+                    _ => Type::Any,
+                }
+            }
+            Expr::SafeIndex { object, index } => {
+                self.check_expr(object);
+                self.check_index(index);
+                Type::Any // Nil whenever the element is missing
+            }
+            Expr::IndexAssign {
+                object,
+                index,
+                value,
+            } => {
+                let object = self.check_expr(object);
+                self.check_index(index);
+                let value = self.check_expr(value);
+                self.check_element_store(&object, &value);
+                value
+            }
+            Expr::ParallelAssign { targets, values } => {
+                let mut types: Vec<Type> =
+                    values.iter().map(|value| self.check_expr(value)).collect();
+                if types.len() != targets.len() {
+                    types = vec![Type::Any; targets.len()]; // Unpacking a single array
+                }
+                for (target, ty) in targets.iter().zip(&types) {
+                    match target {
+                        Expr::Variable(variable) => self.assign(&variable.name, ty),
+                        Expr::Index { object, index } => {
+                            let object = self.check_expr(object);
+                            self.check_index(index);
+                            self.check_element_store(&object, ty);
+                        }
+                        _ => {}
+                    }
+                }
+                Type::Nil
+            }
+            Expr::Match { subject, arms } => {
+                self.check_expr(subject);
+                let mut result = Type::Unknown;
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.check_condition(guard);
+                    }
+                    result = result.join(&self.check_expr(&arm.body));
+                    self.end_scope();
+                }
+                result
+            }
+            Expr::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    let ty = self.check_expr(bound);
+                    if ty.is_known() && ty != Type::Num {
+                        let line = self.line;
+                        self.error(
+                            line,
+                            format!("Range bounds must be numbers but got {}.", ty),
+                        );
+                    }
+                }
+                Type::Range
+            }
+            Expr::Slice {
+                object,
+                start,
+                end,
+                step,
+            } => {
+                let object = self.check_expr(object);
+                for part in [start, end, step].into_iter().flatten() {
+                    self.check_index(part);
+                }
+                match object {
+                    Type::Array(_) | Type::Str => object,
+                    _ => Type::Any,
+                }
+            }
+            Expr::Get { object, .. } => {
+                self.check_expr(object);
+                Type::Any
+            }
+        }
+    }
+
+    // Like `check_expr`, but checks each element of an array literal against
+    // the element type `expected` asks for. Joined together, the elements of
+    // `[1, "a"]` would only say `[any]`, which fits every array type.
+    fn check_expr_as(&mut self, expr: &Expr, expected: Option<&Type>) -> Type {
+        match (expr, expected) {
+            (Expr::Array { elements }, Some(Type::Array(element))) => {
+                for item in elements {
+                    let ty = self.check_expr_as(item, Some(element));
+                    if !ty.fits(element) {
+                        let line = self.line;
+                        self.error(line, format!("Cannot store {} in an array of {}.", ty, element));
+                    }
+                }
+                Type::Array(element.clone())
+            }
+            (Expr::Grouping(group), _) => self.check_expr_as(&group.expression, expected),
+            _ => self.check_expr(expr),
+        }
+    }
+
+    fn check_binary(&mut self, operator: &Token, left: &Type, right: &Type) -> Type {
+        self.line = operator.line;
+        let symbol = &operator.lexeme;
+        match operator.token_type {
+            TokenType::PLUS => {
+                if *left == Type::Str || *right == Type::Str {
+                    return Type::Str; // Anything can be appended to a string
+                }
+                if left.is_known() && right.is_known() {
+                    if *left == Type::Num && *right == Type::Num {
+                        return Type::Num;
+                    }
+                    let message = format!(
+                        "Operator '+' expects two numbers or a string but got {} and {}.",
+                        left, right
+                    );
+                    self.error(operator.line, message);
+                }
+                if *left == Type::Num && *right == Type::Num {
+                    Type::Num
+                } else {
+                    Type::Any
+                }
+            }
+            TokenType::MINUS
+            | TokenType::STAR
+            | TokenType::SLASH
+            | TokenType::PERCENT
+            | TokenType::STAR_STAR
+            | TokenType::AMPERSAND
+            | TokenType::PIPE
+            | TokenType::CARET
+            | TokenType::LESS_LESS
+            | TokenType::GREATER_GREATER => {
+                for operand in [left, right] {
+                    if operand.is_known() && *operand != Type::Num {
+                        let message =
+                            format!("Operator '{}' expects numbers but got {}.", symbol, operand);
+                        self.error(operator.line, message);
+                        break;
+                    }
+                }
+                Type::Num
+            }
+            TokenType::GREATER | TokenType::GREATER_EQUAL | TokenType::LESS | TokenType::LESS_EQUAL => {
+                let ordered = |ty: &Type| matches!(ty, Type::Num | Type::Str | Type::Bool | Type::Array(_));
+                if let Some(operand) = [left, right].into_iter().find(|ty| ty.is_known() && !ordered(ty)) {
+                    let message = format!("Operator '{}' cannot order values of type {}.", symbol, operand);
+                    self.error(operator.line, message);
+                } else if left.is_known() && right.is_known() && stored(left) != stored(right) {
+                    let message = format!("Operator '{}' cannot compare {} with {}.", symbol, left, right);
+                    self.error(operator.line, message);
+                }
+                Type::Bool
+            }
+            TokenType::EQUAL_EQUAL | TokenType::BANG_EQUAL => Type::Bool,
+            _ => Type::Any,
+        }
+    }
+
+    fn check_index(&mut self, index: &Expr) {
+        let ty = self.check_expr(index);
+        if ty.is_known() && ty != Type::Num {
+            let line = self.line;
+            self.error(line, format!("Index must be a number but got {}.", ty));
+        }
+    }
+
+    // `arr[i] = value`
+    fn check_element_store(&mut self, object: &Type, value: &Type) {
+        let line = self.line;
+        match object {
+            Type::Str => self.error(
+                line,
+                "Strings are immutable; build a new string instead.".to_string(),
+            ),
+            Type::Array(element) if !value.fits(element) => {
+                self.error(
+                    line,
+                    format!("Cannot store {} in an array of {}.", value, element),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn check_call(&mut self, callee: &Expr, arguments: &[Expr]) -> Type {
+        let callee_type = self.check_expr(callee);
+        let line = self.line;
+        let params = match &callee_type {
+            Type::Function(Some(signature)) => signature.params.clone().unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let argument_types: Vec<Type> = arguments
+            .iter()
+            .enumerate()
+            .map(|(position, arg)| self.check_expr_as(arg, params.get(position)))
+            .collect();
+        let name = match callee {
+            Expr::Variable(variable) => format!("'{}'", variable.name.lexeme),
+            _ => "function".to_string(),
+        };
+
+        match callee_type {
+            Type::Function(Some(signature)) => {
+                if let Some(params) = &signature.params {
+                    if params.len() != argument_types.len() {
+                        let message = format!(
+                            "{} expects {} arguments but got {}.",
+                            name,
+                            params.len(),
+                            argument_types.len()
+                        );
+                        self.error(line, message);
+                    }
+                    for (position, (argument, param)) in
+                        argument_types.iter().zip(params).enumerate()
+                    {
+                        if !argument.fits(param) {
+                            let message = format!(
+                                "Argument {} of {} must be {} but got {}.",
+                                position + 1,
+                                name,
+                                param,
+                                argument
+                            );
+                            self.error(line, message);
+                        }
+                    }
+                }
+                signature.ret
+            }
+            Type::Num | Type::Str | Type::Bool | Type::Nil | Type::Range | Type::Array(_) => {
+                self.error(
+                    line,
+                    format!("Can only call functions, not {}.", callee_type),
+                );
+                Type::Any
+            }
+            _ => Type::Any,
+        }
+    }
+}
+
+// Unannotated variables don't track element types: arrays are shared and can
+// be changed through any alias
+fn stored(ty: &Type) -> Type {
+    match ty {
+        Type::Array(_) => Type::Array(Box::new(Type::Any)),
+        other => other.clone(),
+    }
+}
+
+// Return types of the built-in functions, so their results can be checked
+fn builtin_type(name: &str) -> Type {
+    if name == "args" {
+        return Type::Array(Box::new(Type::Str)); // Not a function, but also always defined
+    }
+    let ret = match name {
+        "len" | "ord" | "index_of" | "decimal" | "float" | "round" => Type::Num,
+        "substring" | "trim" | "upper" | "lower" | "replace" | "repeat" | "chr" | "str" | "tag" | "format" => {
+            Type::Str
+        }
+        "contains" | "starts_with" | "ends_with" | "any" | "all" => Type::Bool,
+        "split" | "chars" => Type::Array(Box::new(Type::Str)),
+        "map" | "filter" | "sort" | "reverse" | "zip" | "payload" => {
+            Type::Array(Box::new(Type::Any))
+        }
+        "join" | "find" | "reduce" | "next" | "spawn" | "channel" | "recv" | "env" => Type::Any,
+        "send" | "close" | "exit" | "assert" | "help" => Type::Nil,
+        _ => return Type::Any, // Not a built-in, or defined later in the program
+    };
+    Type::Function(Some(Box::new(Signature { params: None, ret })))
+}
+
+// Enum names can be used in annotations anywhere in the program
+fn collect_enums(statements: &[Stmt], enums: &mut HashSet<String>) {
+    for stmt in statements {
+        match stmt {
+            Stmt::Enum { name, .. } => {
+                enums.insert(name.lexeme.clone());
+            }
+            Stmt::Block(body) | Stmt::Function { body, .. } | Stmt::Test { body, .. } => {
+                collect_enums(body, enums)
+            }
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                collect_enums(std::slice::from_ref(then_branch), enums);
+                if let Some(else_branch) = else_branch {
+                    collect_enums(std::slice::from_ref(else_branch), enums);
+                }
+            }
+            Stmt::While { body, .. } | Stmt::For { body, .. } | Stmt::ForIn { body, .. } => {
+                collect_enums(std::slice::from_ref(body), enums)
+            }
+            Stmt::Match { arms, .. } => {
+                for arm in arms {
+                    collect_enums(std::slice::from_ref(&arm.body), enums);
+                }
+            }
+            _ => {}
+        }
+    }
+}
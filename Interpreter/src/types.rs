@@ -0,0 +1,73 @@
+use std::fmt;
+
+// Static types written in annotations (`var x: num`) and used by the type checker
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Any, // Unchecked; every value fits
+    Num,
+    Str,
+    Bool,
+    Nil,
+    Range,
+    Array(Box<Type>),                 // `[num]`
+    Function(Option<Box<Signature>>), // `fun`; the checker knows signatures of declared functions
+    Named(String),                    // An enum declared in the program
+    Unknown,                          // Not known yet, e.g. `var x;`; checker only
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub params: Option<Vec<Type>>, // `None` for built-ins, whose arity varies
+    pub ret: Type,
+}
+
+impl Type {
+    /// Whether a value of type `self` may be stored where `target` is expected.
+    pub fn fits(&self, target: &Type) -> bool {
+        match (self, target) {
+            (Type::Any | Type::Unknown, _) | (_, Type::Any | Type::Unknown) => true,
+            (Type::Array(from), Type::Array(to)) => from.fits(to),
+            (Type::Function(_), Type::Function(None)) => true,
+            (from, to) => from == to,
+        }
+    }
+
+    /// The narrowest type covering both `self` and `other`.
+    pub fn join(&self, other: &Type) -> Type {
+        match (self, other) {
+            (Type::Unknown, t) | (t, Type::Unknown) => t.clone(),
+            (Type::Array(a), Type::Array(b)) => Type::Array(Box::new(a.join(b))),
+            (a, b) if a == b => a.clone(),
+            (Type::Function(_), Type::Function(_)) => Type::Function(None),
+            _ => Type::Any,
+        }
+    }
+
+    /// Whether the checker knows enough about the type to report mistakes.
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Type::Any | Type::Unknown)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any | Type::Unknown => write!(f, "any"),
+            Type::Num => write!(f, "num"),
+            Type::Str => write!(f, "str"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Range => write!(f, "range"),
+            Type::Array(element) => write!(f, "[{}]", element),
+            Type::Function(Some(signature)) => match &signature.params {
+                Some(params) => {
+                    let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                    write!(f, "fun({}): {}", params.join(", "), signature.ret)
+                }
+                None => write!(f, "fun"),
+            },
+            Type::Function(None) => write!(f, "fun"),
+            Type::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
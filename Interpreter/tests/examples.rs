@@ -0,0 +1,13 @@
+use std::process::Command;
+
+// The programs in `examples/` state their own output in `// expect:` comments
+#[test]
+fn examples_print_what_they_expect() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(["test", concat!(env!("CARGO_MANIFEST_DIR"), "/examples")])
+        .output()
+        .expect("Failed to run the interpreter");
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", report);
+    assert!(report.contains(" 0 failed"), "{}", report);
+}